@@ -4,14 +4,14 @@ use std::thread;
 
 use hammer::window::*;
 use hammer::window::event::*;
-// use hammer_core::renderer::*;
+use hammer::core::renderer;
 
 fn main() {
     let thread_handle = thread::spawn(move || {
         let mut window = WindowBuilder::new().title("Window 2").pos(0, 600).build().unwrap();
         window.show();
 
-        // let mut renderer = Renderer::new(&window);
+        renderer::set_target(&window);
 
         // Game like loop
         'event_loop: loop {
@@ -19,37 +19,39 @@ fn main() {
                 match event {
                     Event::Close => break 'event_loop,
                     Event::Resize { w, h, .. } => {
-                        // renderer.resize(w, h);
+                        renderer::resize(w, h);
                     }
                     _ => {}
                 }
             }
 
-            // renderer.clear(1.0, 0.0, 0.0, 1.0);
-            // renderer.present();
+            renderer::clear(1.0, 0.0, 0.0, 1.0);
+            renderer::present();
         }
 
+        renderer::release();
         window.close();
     });
 
     let mut window = WindowBuilder::new().title("Window 1").size(640, 480).build().unwrap();
     window.show();
 
-    // let mut renderer = Renderer::new(&window);
+    renderer::set_target(&window);
 
     // GUI like loop
     for event in window.wait_events() {
         match event {
             Event::Close => break,
             Event::Resize { w, h, .. } => {
-                // renderer.resize(w, h);
-                // renderer.clear(1.0, 1.0, 1.0, 1.0);
-                // renderer.present();
+                renderer::resize(w, h);
+                renderer::clear(1.0, 1.0, 1.0, 1.0);
+                renderer::present();
             }
             _ => {}
         }
     }
 
+    renderer::release();
     window.close();
 
     thread_handle.join().unwrap();
@@ -0,0 +1,125 @@
+//! Packs the images of a directory into texture atlas pages offline.
+//!
+//! ```text
+//! hammer-atlas <dir> <output dir> <name> [--size <w>x<h>] [--padding <n>] [--extrude <n>]
+//! ```
+//!
+//! Writes `<name>_<page>.png` and a TexturePacker style `<name>_<page>.json`
+//! for every page, see `Atlas::save`. Frames are named by their path
+//! relative to `<dir>`, with `/` as separator.
+
+extern crate hammer;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use hammer::core::Error;
+use hammer::core::asset::{AtlasBuilder, Image, ImageRef, ImportSettings};
+
+const EXTENSIONS: [&'static str; 5] = ["png", "jpg", "jpeg", "tga", "bmp"];
+
+fn usage() -> ! {
+    fail("Usage: hammer-atlas <dir> <output dir> <name> [--size <w>x<h>] [--padding <n>] [--extrude <n>]");
+}
+
+fn fail(message: &str) -> ! {
+    let _ = writeln!(io::stderr(), "{}", message);
+    process::exit(1);
+}
+
+fn parse_size(size: &str) -> Option<(i32, i32)> {
+    let mut parts = size.splitn(2, 'x');
+    match (parts.next().and_then(|w| w.parse().ok()), parts.next().and_then(|h| h.parse().ok())) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => Some((w, h)),
+        _ => None,
+    }
+}
+
+fn images(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(current) = stack.pop() {
+        for entry in try!(fs::read_dir(&current)) {
+            let path = try!(entry).path();
+            if try!(fs::metadata(&path)).is_dir() {
+                stack.push(path);
+            } else {
+                let is_image = path.extension()
+                                   .and_then(|extension| extension.to_str())
+                                   .map_or(false, |extension| EXTENSIONS.contains(&&*extension.to_lowercase()));
+                if is_image {
+                    images.push(path);
+                }
+            }
+        }
+    }
+    // Same names and pages on every run
+    images.sort();
+    Ok(images)
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    let mut paths = Vec::new();
+    let mut size = (2048, 2048);
+    let mut padding = 0;
+    let mut extrude = 0;
+    let mut i = 0;
+    while i < args.len() {
+        match &*args[i] {
+            "--size" | "--padding" | "--extrude" => {
+                if i + 1 == args.len() {
+                    usage();
+                }
+                let value = &args[i + 1];
+                match &*args[i] {
+                    "--size" => size = parse_size(value).unwrap_or_else(|| usage()),
+                    "--padding" => padding = value.parse().unwrap_or_else(|_| usage()),
+                    _ => extrude = value.parse().unwrap_or_else(|_| usage()),
+                }
+                i += 1;
+            }
+            _ => paths.push(args[i].clone()),
+        }
+        i += 1;
+    }
+
+    if paths.len() != 3 {
+        usage();
+    }
+
+    let dir = Path::new(&paths[0]);
+    let output = Path::new(&paths[1]);
+    let name = &paths[2];
+
+    let files = images(dir).unwrap_or_else(|e| fail(&format!("Failed to read {}: {}", dir.display(), e)));
+
+    let mut builder = AtlasBuilder::new(size.0, size.1);
+    builder.padding(padding).extrude(extrude);
+
+    // The pages are saved with the default settings, so load the images with them too
+    let settings = ImportSettings::default();
+    for path in files.iter() {
+        let image = File::open(path)
+            .map_err(Error::from)
+            .and_then(|mut file| Image::load_with(&mut file, path, &settings))
+            .unwrap_or_else(|e| fail(&format!("Failed to load {}: {}", path.display(), e)));
+        let relative = path.strip_prefix(dir).unwrap();
+        builder.add(relative.display().to_string().replace("\\", "/"), &ImageRef::new(image));
+    }
+
+    let atlas = builder.build().unwrap_or_else(|e| fail(&format!("Failed to pack {}: {}", dir.display(), e)));
+
+    if let Err(e) = fs::create_dir_all(output) {
+        fail(&format!("Failed to create {}: {}", output.display(), e));
+    }
+    if let Err(e) = atlas.save(output, name) {
+        fail(&format!("Failed to write {}: {}", output.display(), e));
+    }
+
+    println!("Packed {} images into {} pages in {}", files.len(), atlas.pages().len(), output.display());
+}
@@ -0,0 +1,96 @@
+//! Packs a directory into one file for `asset::mount_pack`.
+//!
+//! ```text
+//! hammer-pack <dir> <output> [--prefix <prefix>]
+//! ```
+//!
+//! Files are named like the paths the game loads them with, which is
+//! `<dir>/<file>` unless another prefix is given, e.g. `./assets` packs
+//! `assets/hero.png`.
+
+extern crate hammer;
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use hammer::core::vfs::{Pack, PackBuilder};
+
+fn usage() -> ! {
+    fail("Usage: hammer-pack <dir> <output> [--prefix <prefix>]");
+}
+
+fn fail(message: &str) -> ! {
+    let _ = writeln!(io::stderr(), "{}", message);
+    process::exit(1);
+}
+
+// Asset ids use `/` and don't start with `./`, see `asset::path_to_id`
+fn normalize_prefix(prefix: &str) -> String {
+    let mut prefix = prefix.replace("\\", "/");
+    while prefix.starts_with("./") {
+        prefix = prefix[2..].trim_left_matches('/').to_string();
+    }
+    if prefix == "." {
+        prefix.clear();
+    }
+    prefix
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    let mut paths = Vec::new();
+    let mut prefix = None;
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "--prefix" {
+            i += 1;
+            if i == args.len() {
+                usage();
+            }
+            prefix = Some(args[i].clone());
+        } else {
+            paths.push(args[i].clone());
+        }
+        i += 1;
+    }
+
+    if paths.len() != 2 {
+        usage();
+    }
+
+    let dir = &paths[0];
+    let output = &paths[1];
+    let prefix = match prefix {
+        Some(prefix) => normalize_prefix(&prefix),
+        None => {
+            let dir = normalize_prefix(dir.trim_right_matches(|c| c == '/' || c == '\\'));
+            if dir.is_empty() { dir } else { format!("{}/", dir) }
+        }
+    };
+
+    let mut builder = PackBuilder::new();
+    if let Err(e) = builder.add_dir(dir, &prefix) {
+        fail(&format!("Failed to read {}: {}", dir, e));
+    }
+
+    if let Err(e) = builder.write(output) {
+        fail(&format!("Failed to write {}: {}", output, e));
+    }
+
+    // Read it back, so a broken pack never ships
+    match Pack::open(output) {
+        Ok(pack) => {
+            let names = pack.names();
+            for name in names.iter() {
+                if let Err(e) = pack.read(name) {
+                    fail(&e.to_string());
+                }
+            }
+            println!("Packed {} files into {}", names.len(), output);
+        }
+
+        Err(e) => fail(&format!("Failed to read back {}: {}", output, e)),
+    }
+}
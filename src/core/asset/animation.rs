@@ -1,15 +1,30 @@
+use std::io::Read;
+use std::path::Path;
+
 use super::*;
 
+use Error;
+
+use input;
+use math::Scalar;
+
 pub type AnimationRef = AssetRef<Animation>;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum WrapMode {
     Loop,
+    /// Stops and rewinds to the first frame at the end.
     Once,
+    /// Plays forward and backward again.
+    PingPong,
+    /// Keeps showing the last frame at the end.
+    ClampForever,
 }
 
 pub struct Animation {
     frames: Vec<Frame>,
+    // `None` falls back to `1 / fps`
+    durations: Vec<Option<Scalar>>,
     fps: u16,
     wrap_mode: WrapMode,
 }
@@ -20,10 +35,20 @@ impl Asset for Animation {
     }
 }
 
+/// Loads Aseprite or TexturePacker JSON exports. The referenced sheet is
+/// loaded with `asset::<Image>::load`, and every frame tag is registered as
+/// its own animation with id `<path>#<tag>`, updated in place on reload.
+impl Loadable for Animation {
+    fn load<R: Read>(reader: &mut R, path: &Path) -> Result<Self, Error> {
+        sheet::load_animation(reader, path)
+    }
+}
+
 impl Animation {
     pub fn new() -> Animation {
         Animation {
             frames: Vec::new(),
+            durations: Vec::new(),
             fps: 60,
             wrap_mode: WrapMode::Loop,
         }
@@ -31,6 +56,18 @@ impl Animation {
 
     pub fn add_frame(&mut self, frame: Frame) {
         self.frames.push(frame);
+        self.durations.push(None);
+    }
+
+    /// Adds a frame which lasts `duration` seconds regardless of `fps`.
+    pub fn add_frame_with_duration(&mut self, frame: Frame, duration: Scalar) {
+        self.frames.push(frame);
+        self.durations.push(Some(duration));
+    }
+
+    /// Duration of the frame at `index` in seconds, `None` if there is no such frame.
+    pub fn frame_duration(&self, index: usize) -> Option<Scalar> {
+        self.durations.get(index).map(|duration| duration.unwrap_or(1.0 / self.fps as Scalar))
     }
 
     pub fn frames(&self) -> &[Frame] {
@@ -53,3 +90,300 @@ impl Animation {
         self.fps = fps;
     }
 }
+
+// Frames shorter than this are treated as this long, so zero durations can't stall `advance`.
+const MIN_FRAME_DURATION: Scalar = 1e-4;
+
+/// Plays an `Animation` by advancing through its frames over time.
+pub struct AnimationPlayer {
+    animation: AnimationRef,
+    frame: usize,
+    // Time spent in the current frame
+    time: Scalar,
+    speed: Scalar,
+    is_playing: bool,
+    is_forward: bool,
+    events: Vec<(usize, Box<FnMut(usize)>)>,
+}
+
+impl AnimationPlayer {
+    pub fn new(animation: AnimationRef) -> AnimationPlayer {
+        AnimationPlayer {
+            animation: animation,
+            frame: 0,
+            time: 0.0,
+            speed: 1.0,
+            is_playing: true,
+            is_forward: true,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn animation(&self) -> &AnimationRef {
+        &self.animation
+    }
+
+    /// Switches to another animation and starts it from the first frame.
+    pub fn set_animation(&mut self, animation: AnimationRef) {
+        self.animation = animation;
+        self.rewind();
+        self.is_playing = true;
+    }
+
+    pub fn play(&mut self) {
+        self.is_playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.is_playing = false;
+    }
+
+    pub fn stop(&mut self) {
+        self.is_playing = false;
+        self.rewind();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.is_playing
+    }
+
+    pub fn speed(&self) -> Scalar {
+        self.speed
+    }
+
+    /// Scales the elapsed time, e.g. 2.0 plays twice as fast.
+    pub fn set_speed(&mut self, speed: Scalar) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn frame_index(&self) -> usize {
+        self.frame
+    }
+
+    pub fn frame(&self) -> Option<Frame> {
+        self.animation.read().frames().get(self.frame).cloned()
+    }
+
+    /// Calls `f` with the frame index every time the player enters frame `index`.
+    ///
+    /// Starting or rewinding doesn't enter the first frame, so `f` isn't called
+    /// for it until the animation loops or ping-pongs back to it.
+    pub fn on_frame<F: FnMut(usize) + 'static>(&mut self, index: usize, f: F) {
+        self.events.push((index, Box::new(f)));
+    }
+
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+    }
+
+    /// Advances by one fixed update, see `input::delta`.
+    pub fn update(&mut self) {
+        self.advance(input::delta());
+    }
+
+    pub fn advance(&mut self, dt: Scalar) {
+        if !self.is_playing {
+            return;
+        }
+
+        let animation = self.animation.clone();
+        let animation = animation.read();
+        let len = animation.frames().len();
+        if len == 0 {
+            return;
+        }
+
+        // A hot reload can replace the animation with a shorter one
+        if self.frame >= len {
+            self.rewind();
+        }
+
+        self.time += dt * self.speed;
+
+        while self.is_playing {
+            let duration = match animation.frame_duration(self.frame) {
+                Some(duration) => duration.max(MIN_FRAME_DURATION),
+                None => break,
+            };
+            if self.time < duration {
+                break;
+            }
+
+            self.time -= duration;
+
+            if !self.step(animation.wrap_mode(), len) {
+                break;
+            }
+        }
+    }
+
+    // Moves to the next frame, returns false if the player stays where it is.
+    fn step(&mut self, wrap_mode: WrapMode, len: usize) -> bool {
+        let next = match wrap_mode {
+            WrapMode::Loop => (self.frame + 1) % len,
+
+            WrapMode::Once => {
+                if self.frame + 1 < len {
+                    self.frame + 1
+                } else {
+                    self.stop();
+                    return false;
+                }
+            }
+
+            WrapMode::ClampForever => {
+                if self.frame + 1 < len {
+                    self.frame + 1
+                } else {
+                    self.time = 0.0;
+                    return false;
+                }
+            }
+
+            WrapMode::PingPong => {
+                if len == 1 {
+                    0
+                } else if self.is_forward {
+                    if self.frame + 1 < len {
+                        self.frame + 1
+                    } else {
+                        self.is_forward = false;
+                        self.frame - 1
+                    }
+                } else if self.frame > 0 {
+                    self.frame - 1
+                } else {
+                    self.is_forward = true;
+                    self.frame + 1
+                }
+            }
+        };
+
+        self.frame = next;
+
+        for &mut (index, ref mut f) in self.events.iter_mut() {
+            if index == next {
+                f(next);
+            }
+        }
+
+        true
+    }
+
+    fn rewind(&mut self) {
+        self.frame = 0;
+        self.time = 0.0;
+        self.is_forward = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use asset::{asset, Animation, AnimationRef, AnimationPlayer, Frame, Image, ImageRef, WrapMode};
+    use math::{Rect, vector};
+
+    fn animation(frames: usize, wrap_mode: WrapMode) -> Animation {
+        let image = ImageRef::new(Image::new(1, 1));
+        let mut animation = Animation::new();
+        for _ in 0..frames {
+            let frame = Frame::new(image.clone(), Rect::with_min_size(vector(0.0, 0.0), vector(1.0, 1.0)));
+            animation.add_frame_with_duration(frame, 1.0);
+        }
+        animation.set_wrap_mode(wrap_mode);
+        animation
+    }
+
+    fn player(frames: usize, wrap_mode: WrapMode) -> AnimationPlayer {
+        AnimationPlayer::new(AnimationRef::new(animation(frames, wrap_mode)))
+    }
+
+    fn frames(player: &mut AnimationPlayer, steps: usize) -> Vec<usize> {
+        (0..steps).map(|_| {
+            player.advance(1.0);
+            player.frame_index()
+        }).collect()
+    }
+
+    #[test]
+    fn loop_wraps_around() {
+        let mut player = player(3, WrapMode::Loop);
+        assert_eq!(frames(&mut player, 4), vec![1, 2, 0, 1]);
+        assert!(player.is_playing());
+    }
+
+    #[test]
+    fn once_stops_and_rewinds() {
+        let mut player = player(3, WrapMode::Once);
+        assert_eq!(frames(&mut player, 3), vec![1, 2, 0]);
+        assert!(!player.is_playing());
+    }
+
+    #[test]
+    fn clamp_forever_keeps_last_frame() {
+        let mut player = player(3, WrapMode::ClampForever);
+        assert_eq!(frames(&mut player, 4), vec![1, 2, 2, 2]);
+        assert!(player.is_playing());
+    }
+
+    #[test]
+    fn ping_pong_turns_at_both_ends() {
+        let mut player = player(3, WrapMode::PingPong);
+        assert_eq!(frames(&mut player, 6), vec![1, 2, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn speed_scales_time() {
+        let mut player = player(4, WrapMode::Loop);
+        player.set_speed(2.0);
+        player.advance(1.0);
+        assert_eq!(player.frame_index(), 2);
+    }
+
+    #[test]
+    fn events_fire_when_entering_frame() {
+        let mut player = player(3, WrapMode::Loop);
+        let first = Rc::new(Cell::new(0));
+        let last = Rc::new(Cell::new(0));
+        {
+            let first = first.clone();
+            player.on_frame(0, move |_| first.set(first.get() + 1));
+            let last = last.clone();
+            player.on_frame(2, move |index| last.set(last.get() + index));
+        }
+
+        // Starting on frame 0 doesn't fire its event
+        player.advance(0.5);
+        assert_eq!(first.get(), 0);
+
+        // One step per frame, even if several happen in one advance
+        player.advance(5.5);
+        assert_eq!(first.get(), 2);
+        assert_eq!(last.get(), 4);
+    }
+
+    #[test]
+    fn shorter_animation_rewinds() {
+        let mut player = player(3, WrapMode::Loop);
+        player.advance(2.0);
+        assert_eq!(player.frame_index(), 2);
+
+        *player.animation().write() = animation(1, WrapMode::Loop);
+        player.advance(0.5);
+        assert_eq!(player.frame_index(), 0);
+        assert!(player.frame().is_some());
+    }
+
+    #[test]
+    fn update_replaces_held_tag() {
+        asset::<Animation>::update("animation-test.json#walk", animation(2, WrapMode::Loop));
+        let held = asset::<Animation>::get("animation-test.json#walk").unwrap();
+
+        // What reloading the sheet does with its tags
+        asset::<Animation>::update("animation-test.json#walk", animation(3, WrapMode::PingPong));
+        assert_eq!(held.read().frames().len(), 3);
+        assert_eq!(held.read().wrap_mode(), WrapMode::PingPong);
+    }
+}
@@ -0,0 +1,445 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use serde_json;
+
+use super::*;
+
+use Error;
+
+use math::{Rect, Scalar, vector};
+
+pub struct AtlasBuilder {
+    page_w: i32,
+    page_h: i32,
+    padding: i32,
+    extrude: i32,
+    images: Vec<(String, ImageRef)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(page_w: i32, page_h: i32) -> AtlasBuilder {
+        AtlasBuilder {
+            page_w: page_w,
+            page_h: page_h,
+            padding: 0,
+            extrude: 0,
+            images: Vec::new(),
+        }
+    }
+
+    /// Empty pixels between two packed images.
+    pub fn padding(&mut self, padding: i32) -> &mut Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Repeats the border pixels of every image this many times, which avoids
+    /// bleeding of the neighbours when the frames are filtered or scaled.
+    pub fn extrude(&mut self, extrude: i32) -> &mut Self {
+        self.extrude = extrude;
+        self
+    }
+
+    pub fn add<S: Into<String>>(&mut self, name: S, image: &ImageRef) -> &mut Self {
+        self.images.push((name.into(), image.clone()));
+        self
+    }
+
+    pub fn build(&self) -> Result<Atlas, Error> {
+        let border = self.extrude * 2 + self.padding;
+
+        // Pack the tallest images first, which keeps the skyline flat
+        let mut order = (0..self.images.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            let (aw, ah) = self.images[a].1.read().size();
+            let (bw, bh) = self.images[b].1.read().size();
+            (bh, bw).cmp(&(ah, aw))
+        });
+
+        let mut pages: Vec<(Skyline, Vec<u8>)> = Vec::new();
+        let mut placements = Vec::with_capacity(self.images.len());
+
+        for index in order {
+            let (ref name, ref image) = self.images[index];
+            let image = image.read();
+            let (w, h) = image.size();
+
+            if w <= 0 || h <= 0 {
+                return Err(Error::InvalidInput(format!("Image {} is empty, atlases can't hold it", name)));
+            }
+
+            if image.format() != PixelFormat::Rgba8 {
+                return Err(Error::InvalidInput(format!("Image {} is {:?}, atlases only hold Rgba8 images", name, image.format())));
+            }
+
+            if w + border > self.page_w || h + border > self.page_h {
+                return Err(Error::InvalidInput(format!("Image {} ({}x{}) doesn't fit into a {}x{} atlas page",
+                                                       name, w, h, self.page_w, self.page_h)));
+            }
+
+            let mut found = None;
+            for (page, &mut (ref mut skyline, _)) in pages.iter_mut().enumerate() {
+                if let Some(pos) = skyline.insert(w + border, h + border) {
+                    found = Some((page, pos));
+                    break;
+                }
+            }
+
+            let (page, (x, y)) = match found {
+                Some(found) => found,
+                None => {
+                    let mut skyline = Skyline::new(self.page_w, self.page_h);
+                    let pos = skyline.insert(w + border, h + border).unwrap();
+                    pages.push((skyline, vec![0; (self.page_w * self.page_h * 4) as usize]));
+                    (pages.len() - 1, pos)
+                }
+            };
+
+            let x = x + self.extrude;
+            let y = y + self.extrude;
+            self.copy_extruded(&mut pages[page].1, image.data(), x, y, w, h);
+
+            placements.push((index, page, Rect::with_min_size(vector(x as Scalar, y as Scalar),
+                                                             vector(w as Scalar, h as Scalar))));
+        }
+
+        let pages = pages.into_iter()
+                         .map(|(_, data)| ImageRef::new(Image::from_rgba(self.page_w, self.page_h, data)))
+                         .collect::<Vec<_>>();
+
+        placements.sort_by_key(|&(index, _, _)| index);
+
+        let mut entries = Vec::with_capacity(placements.len());
+        let mut frames = HashMap::new();
+        for (index, page, region) in placements {
+            let name = self.images[index].0.clone();
+            let frame = FrameRef::new(Frame::new(pages[page].clone(), region));
+            frames.insert(name.clone(), frame.clone());
+            entries.push(AtlasEntry {
+                name: name,
+                page: page,
+                frame: frame,
+            });
+        }
+
+        info!("Packed {} images into {} atlas pages", entries.len(), pages.len());
+
+        Ok(Atlas {
+            pages: pages,
+            entries: entries,
+            frames: frames,
+        })
+    }
+
+    fn copy_extruded(&self, page: &mut [u8], data: &[u8], x: i32, y: i32, w: i32, h: i32) {
+        let e = self.extrude;
+        for dy in -e..h + e {
+            let sy = cmp::min(cmp::max(dy, 0), h - 1);
+            for dx in -e..w + e {
+                let sx = cmp::min(cmp::max(dx, 0), w - 1);
+                let src = ((sy * w + sx) * 4) as usize;
+                let dst = (((y + dy) * self.page_w + x + dx) * 4) as usize;
+                page[dst..dst + 4].copy_from_slice(&data[src..src + 4]);
+            }
+        }
+    }
+}
+
+struct AtlasEntry {
+    name: String,
+    page: usize,
+    frame: FrameRef,
+}
+
+pub struct Atlas {
+    pages: Vec<ImageRef>,
+    entries: Vec<AtlasEntry>,
+    frames: HashMap<String, FrameRef>,
+}
+
+impl Atlas {
+    pub fn pages(&self) -> &[ImageRef] {
+        &self.pages
+    }
+
+    pub fn frame(&self, name: &str) -> Option<&FrameRef> {
+        self.frames.get(name)
+    }
+
+    /// Registers every frame with `asset::<Frame>::set` under its name.
+    pub fn register(&self) {
+        for entry in self.entries.iter() {
+            asset::<Frame>::set(entry.name.clone(), entry.frame.read().clone());
+        }
+    }
+
+    /// Writes every page as `<name>_<page>.png` into `dir`, each with a
+    /// TexturePacker style `<name>_<page>.json` describing its frames.
+    pub fn save<P: AsRef<Path>>(&self, dir: P, name: &str) -> Result<(), Error> {
+        let dir = dir.as_ref();
+
+        for (index, page) in self.pages.iter().enumerate() {
+            let image_name = format!("{}_{}.png", name, index);
+            let page = page.read();
+            let (w, h) = page.size();
+
+            try!(page.save_png(dir.join(&image_name)));
+
+            let frames = self.entries.iter().filter(|entry| entry.page == index).map(|entry| {
+                let frame = entry.frame.read();
+                let region = frame.region();
+                let (fw, fh) = (region.size().x as i32, region.size().y as i32);
+                // Sheets count rows from the top, images from the bottom
+                let rect = SheetRect {
+                    x: region.left() as i32,
+                    y: h - region.top() as i32,
+                    w: fw,
+                    h: fh,
+                };
+                SheetFrame {
+                    filename: entry.name.clone(),
+                    frame: rect,
+                    rotated: false,
+                    trimmed: false,
+                    sprite_source_size: SheetRect { x: 0, y: 0, w: fw, h: fh },
+                    source_size: SheetSize { w: fw, h: fh },
+                    pivot: SheetPivot { x: 0.0, y: 1.0 },
+                }
+            }).collect::<Vec<_>>();
+
+            let sheet = Sheet {
+                frames: frames,
+                meta: SheetMeta {
+                    app: "hammer".to_string(),
+                    image: image_name,
+                    size: SheetSize { w: w, h: h },
+                    scale: "1".to_string(),
+                },
+            };
+
+            let json = try!(serde_json::to_string_pretty(&sheet));
+            let mut file = try!(File::create(dir.join(format!("{}_{}.json", name, index))));
+            try!(file.write_all(json.as_bytes()));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SheetRect {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SheetSize {
+    w: i32,
+    h: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SheetPivot {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SheetFrame {
+    filename: String,
+    frame: SheetRect,
+    rotated: bool,
+    trimmed: bool,
+    #[serde(rename="spriteSourceSize")]
+    sprite_source_size: SheetRect,
+    #[serde(rename="sourceSize")]
+    source_size: SheetSize,
+    pivot: SheetPivot,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SheetMeta {
+    app: String,
+    image: String,
+    size: SheetSize,
+    scale: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sheet {
+    frames: Vec<SheetFrame>,
+    meta: SheetMeta,
+}
+
+/// Skyline bottom-left packer, the skyline is a list of `(x, y, width)`
+/// segments which covers the whole page width.
+struct Skyline {
+    w: i32,
+    h: i32,
+    nodes: Vec<(i32, i32, i32)>,
+}
+
+impl Skyline {
+    fn new(w: i32, h: i32) -> Skyline {
+        Skyline {
+            w: w,
+            h: h,
+            nodes: vec![(0, 0, w)],
+        }
+    }
+
+    fn insert(&mut self, w: i32, h: i32) -> Option<(i32, i32)> {
+        let mut best: Option<(usize, i32, i32)> = None;
+
+        for index in 0..self.nodes.len() {
+            if let Some(y) = self.fit(index, w, h) {
+                let node_width = self.nodes[index].2;
+                let better = match best {
+                    Some((_, best_y, best_width)) => y < best_y || (y == best_y && node_width < best_width),
+                    None => true,
+                };
+                if better {
+                    best = Some((index, y, node_width));
+                }
+            }
+        }
+
+        best.map(|(index, y, _)| {
+            let x = self.nodes[index].0;
+            self.nodes.insert(index, (x, y + h, w));
+
+            // Shrink or remove the segments now covered by the new one
+            let mut i = index + 1;
+            while i < self.nodes.len() {
+                let (prev_x, _, prev_w) = self.nodes[i - 1];
+                let prev_right = prev_x + prev_w;
+                let (node_x, node_y, node_w) = self.nodes[i];
+                if node_x < prev_right {
+                    let shrink = prev_right - node_x;
+                    if node_w <= shrink {
+                        self.nodes.remove(i);
+                        continue;
+                    }
+                    self.nodes[i] = (node_x + shrink, node_y, node_w - shrink);
+                }
+                break;
+            }
+
+            // Merge neighbours on the same height
+            let mut i = 0;
+            while i + 1 < self.nodes.len() {
+                if self.nodes[i].1 == self.nodes[i + 1].1 {
+                    self.nodes[i].2 += self.nodes[i + 1].2;
+                    self.nodes.remove(i + 1);
+                } else {
+                    i += 1;
+                }
+            }
+
+            (x, y)
+        })
+    }
+
+    fn fit(&self, index: usize, w: i32, h: i32) -> Option<i32> {
+        let x = self.nodes[index].0;
+        if x + w > self.w {
+            return None;
+        }
+
+        let mut y = 0;
+        let mut width_left = w;
+        let mut i = index;
+        while width_left > 0 {
+            let (_, node_y, node_w) = self.nodes[i];
+            y = cmp::max(y, node_y);
+            if y + h > self.h {
+                return None;
+            }
+            width_left -= node_w;
+            i += 1;
+        }
+
+        Some(y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use asset::{AtlasBuilder, Image, ImageRef};
+    use math::vector;
+
+    use super::Skyline;
+
+    fn overlaps(a: (i32, i32, i32, i32), b: (i32, i32, i32, i32)) -> bool {
+        a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+    }
+
+    #[test]
+    fn skyline_fills_page() {
+        let mut skyline = Skyline::new(4, 4);
+        let mut placed = Vec::new();
+        for _ in 0..4 {
+            let (x, y) = skyline.insert(2, 2).unwrap();
+            placed.push((x, y, 2, 2));
+        }
+        assert_eq!(skyline.insert(1, 1), None);
+
+        for (i, &a) in placed.iter().enumerate() {
+            assert!(a.0 >= 0 && a.1 >= 0 && a.0 + a.2 <= 4 && a.1 + a.3 <= 4);
+            for &b in placed[i + 1..].iter() {
+                assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn skyline_prefers_lowest_position() {
+        let mut skyline = Skyline::new(8, 8);
+        assert_eq!(skyline.insert(3, 4), Some((0, 0)));
+        assert_eq!(skyline.insert(3, 2), Some((3, 0)));
+        // Fits next to both, on the ground
+        assert_eq!(skyline.insert(2, 5), Some((6, 0)));
+        // Lowest spot left is on top of the short one
+        assert_eq!(skyline.insert(3, 1), Some((3, 2)));
+        assert_eq!(skyline.insert(9, 1), None);
+    }
+
+    #[test]
+    fn build_extrudes_borders() {
+        let data = vec![
+            1, 1, 1, 255,   2, 2, 2, 255,
+            3, 3, 3, 255,   4, 4, 4, 255,
+        ];
+        let image = ImageRef::new(Image::from_rgba(2, 2, data));
+        let atlas = AtlasBuilder::new(4, 4).extrude(1).add("a", &image).build().unwrap();
+
+        let frame = atlas.frame("a").unwrap().read();
+        assert_eq!(frame.region().min(), vector(1.0, 1.0));
+        assert_eq!(frame.region().size(), vector(2.0, 2.0));
+
+        let page = atlas.pages()[0].read();
+        let expected = [
+            [1, 1, 2, 2],
+            [1, 1, 2, 2],
+            [3, 3, 4, 4],
+            [3, 3, 4, 4],
+        ];
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(page.pixel(x, y)[0], expected[y as usize][x as usize], "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn build_rejects_empty_images() {
+        let image = ImageRef::new(Image::from_rgba(0, 0, Vec::new()));
+        assert!(AtlasBuilder::new(4, 4).add("empty", &image).build().is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use std::f32;
+use std::mem;
+
+/// Layout of the pixels in `Image::data`. Float formats store native endian
+/// `f32`, or IEEE half floats for `Rgba16F`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    R8,
+    Rg8,
+    Rgb8,
+    Rgba8,
+    Rgba16F,
+    R32F,
+}
+
+impl PixelFormat {
+    /// Parses the names used in `.import` sidecars, e.g. `"rgba16f"`.
+    pub fn parse(name: &str) -> Option<PixelFormat> {
+        match name {
+            "r8" => Some(PixelFormat::R8),
+            "rg8" => Some(PixelFormat::Rg8),
+            "rgb8" => Some(PixelFormat::Rgb8),
+            "rgba8" => Some(PixelFormat::Rgba8),
+            "rgba16f" => Some(PixelFormat::Rgba16F),
+            "r32f" => Some(PixelFormat::R32F),
+            _ => None,
+        }
+    }
+
+    pub fn channels(self) -> usize {
+        match self {
+            PixelFormat::R8 | PixelFormat::R32F => 1,
+            PixelFormat::Rg8 => 2,
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Rgba8 | PixelFormat::Rgba16F => 4,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::R8 => 1,
+            PixelFormat::Rg8 => 2,
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Rgba8 | PixelFormat::R32F => 4,
+            PixelFormat::Rgba16F => 8,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        match self {
+            PixelFormat::Rgba16F | PixelFormat::R32F => true,
+            _ => false,
+        }
+    }
+
+    pub fn has_alpha(self) -> bool {
+        self.channels() == 4
+    }
+
+    /// Decodes one pixel, missing channels read as 0 and alpha as 1 like GL samples them.
+    pub fn read(self, bytes: &[u8]) -> [f32; 4] {
+        let mut value = [0.0, 0.0, 0.0, 1.0];
+        match self {
+            PixelFormat::Rgba16F => {
+                for c in 0..4 {
+                    value[c] = f16_to_f32(bytes[c * 2] as u16 | (bytes[c * 2 + 1] as u16) << 8);
+                }
+            }
+
+            PixelFormat::R32F => {
+                value[0] = unsafe { mem::transmute::<[u8; 4], f32>([bytes[0], bytes[1], bytes[2], bytes[3]]) };
+            }
+
+            _ => {
+                for c in 0..self.channels() {
+                    value[c] = bytes[c] as f32 / 255.0;
+                }
+            }
+        }
+        value
+    }
+
+    /// Encodes the channels of `value` this format has into `bytes`.
+    pub fn write(self, value: [f32; 4], bytes: &mut [u8]) {
+        match self {
+            PixelFormat::Rgba16F => {
+                for c in 0..4 {
+                    let half = f32_to_f16(value[c]);
+                    bytes[c * 2] = half as u8;
+                    bytes[c * 2 + 1] = (half >> 8) as u8;
+                }
+            }
+
+            PixelFormat::R32F => {
+                let float = unsafe { mem::transmute::<f32, [u8; 4]>(value[0]) };
+                bytes[..4].copy_from_slice(&float);
+            }
+
+            _ => {
+                for c in 0..self.channels() {
+                    let v = if value[c] < 0.0 { 0.0 } else if value[c] > 1.0 { 1.0 } else { value[c] };
+                    bytes[c] = (v * 255.0 + 0.5) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Rounds to the nearest half float, too large values become infinity.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = unsafe { mem::transmute::<f32, u32>(value) };
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7fffff;
+
+    if exponent == 0xff {
+        // Infinity stays infinity, NaN stays NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if exponent <= 0 {
+        // Subnormal half, or zero if even that is too small
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x800000;
+        let shift = (14 - exponent) as u32;
+        let round = ((mantissa >> (shift - 1)) & 1) as u16;
+        return sign | ((mantissa >> shift) as u16 + round);
+    }
+
+    // A carry out of the mantissa correctly bumps the exponent
+    let half = sign | (exponent as u16) << 10 | (mantissa >> 13) as u16;
+    half + ((mantissa >> 12) & 1) as u16
+}
+
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f => if mantissa == 0.0 { f32::INFINITY } else { f32::NAN },
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+
+    use super::{PixelFormat, f16_to_f32, f32_to_f16};
+
+    #[test]
+    fn f16_exact_values() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        // Smallest subnormal
+        assert_eq!(f32_to_f16(2f32.powi(-24)), 0x0001);
+
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+    }
+
+    #[test]
+    fn f16_special_values() {
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert_eq!(f32_to_f16(1e-10), 0x0000);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+    }
+
+    #[test]
+    fn f16_rounds_to_nearest() {
+        // 1 + 2^-11 is halfway between 1 and the next half, 1 + 2^-10
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-10) * 0.75), 0x3c01);
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-10) * 0.25), 0x3c00);
+        // Rounding up the largest mantissa carries into the exponent
+        assert_eq!(f32_to_f16(2.0 - 2f32.powi(-12)), 0x4000);
+    }
+
+    #[test]
+    fn f16_round_trips_every_finite_half() {
+        for half in 0..0x10000u32 {
+            let half = half as u16;
+            if (half >> 10) & 0x1f == 0x1f {
+                continue;
+            }
+            assert_eq!(f32_to_f16(f16_to_f32(half)), half, "{:#06x}", half);
+        }
+    }
+
+    #[test]
+    fn pixel_formats_round_trip() {
+        let value = [0.25, 0.5, 0.75, 1.0];
+        for &format in [PixelFormat::R8, PixelFormat::Rg8, PixelFormat::Rgb8, PixelFormat::Rgba8,
+                        PixelFormat::Rgba16F, PixelFormat::R32F].iter() {
+            let mut bytes = vec![0; format.bytes_per_pixel()];
+            format.write(value, &mut bytes);
+            let read = format.read(&bytes);
+            for c in 0..4 {
+                let expected = if c < format.channels() { value[c] } else if c == 3 { 1.0 } else { 0.0 };
+                assert!((read[c] - expected).abs() < 1.0 / 255.0, "{:?} channel {}: {}", format, c, read[c]);
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
+use std::cmp;
+use std::io::Read;
 use std::path::Path;
+use std::os::raw::c_int;
 use std::ptr;
-use std::ffi::CString;
 
 use super::*;
 
@@ -12,22 +14,105 @@ use util::stb_image::*;
 use util::cstr_to_string;
 
 use util::counter::Counter;
+use util::png;
 
 lazy_static! {
     static ref COUNTER: Counter<usize> = Counter::new(0);
 }
 
 pub type ImageRef = AssetRef<Image>;
+pub type WeakImageRef = WeakAssetRef<Image>;
 pub type FrameRef = AssetRef<Frame>;
 
 pub struct Image {
     id: usize,
     w: i32,
     h: i32,
+    format: PixelFormat,
+    // How `data` was converted from the file, `save_png` reverts it.
+    settings: ImportSettings,
     data: Vec<u8>,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ResizeFilter {
+    Nearest,
+    Bilinear,
+}
+
 impl Image {
+    /// Creates a transparent image.
+    pub fn new(w: i32, h: i32) -> Image {
+        Image::from_rgba(w, h, vec![0; (w * h * 4) as usize])
+    }
+
+    /// Creates an image from pre-multiplied RGBA pixels, rows from bottom to top.
+    pub fn from_rgba(w: i32, h: i32, data: Vec<u8>) -> Image {
+        Image::from_rgba_with(w, h, data, ImportSettings::default())
+    }
+
+    /// Creates an image from pixels which were converted with `settings`.
+    pub fn from_rgba_with(w: i32, h: i32, data: Vec<u8>, settings: ImportSettings) -> Image {
+        Image::from_pixels(w, h, PixelFormat::Rgba8, data, settings)
+    }
+
+    /// Creates an image from tightly packed `format` pixels, rows from bottom to top.
+    pub fn from_pixels(w: i32, h: i32, format: PixelFormat, data: Vec<u8>, settings: ImportSettings) -> Image {
+        assert_eq!(data.len(), w as usize * h as usize * format.bytes_per_pixel());
+        Image {
+            id: COUNTER.next(),
+            w: w,
+            h: h,
+            format: format,
+            settings: settings,
+            data: data,
+        }
+    }
+
+    /// Decodes an image file, see `ImportSettings` for how the pixels are converted.
+    pub fn load_with<R: Read>(reader: &mut R, path: &Path, settings: &ImportSettings) -> Result<Image, Error> {
+        let mut bytes = Vec::new();
+        try!(reader.read_to_end(&mut bytes));
+        unsafe {
+            let is_hdr = stbi_is_hdr_from_memory(bytes.as_ptr(), bytes.len() as c_int) != 0;
+            let format = settings.format.unwrap_or(if is_hdr { PixelFormat::Rgba16F } else { PixelFormat::Rgba8 });
+            let channels = format.channels();
+
+            let mut w = 0;
+            let mut h = 0;
+            let pixels = if is_hdr && format.is_float() {
+                let data = stbi_loadf_from_memory(bytes.as_ptr(), bytes.len() as c_int, &mut w, &mut h, ptr::null_mut(), channels as c_int);
+                if data.is_null() {
+                    return Err(Error::Decode(format!("{}: {}", path.display(), cstr_to_string(stbi_failure_reason()))));
+                }
+                let floats = ::std::slice::from_raw_parts(data, w as usize * h as usize * channels);
+                let pixels = floats_to_pixels(format, floats);
+                stbi_image_free(data as *mut stbi_uc);
+                pixels
+            } else {
+                // stb_image converts HDR files to 8 bits itself
+                let data = stbi_load_from_memory(bytes.as_ptr(), bytes.len() as c_int, &mut w, &mut h, ptr::null_mut(), channels as c_int);
+                if data.is_null() {
+                    return Err(Error::Decode(format!("{}: {}", path.display(), cstr_to_string(stbi_failure_reason()))));
+                }
+                let data_bytes = ::std::slice::from_raw_parts(data, w as usize * h as usize * channels);
+                let pixels = if format.is_float() {
+                    // e.g. an 8 bit heightmap loaded as R32F
+                    let floats = data_bytes.iter().map(|&b| b as f32 / 255.0).collect::<Vec<_>>();
+                    floats_to_pixels(format, &floats)
+                } else {
+                    data_bytes.to_vec()
+                };
+                stbi_image_free(data);
+                pixels
+            };
+
+            let pixels = settings.apply(w, h, format, &pixels);
+            Ok(Image::from_pixels(w, h, format, pixels, *settings))
+        }
+    }
+
+    /// Changes whenever the pixels change, so renderers know to upload them again.
     pub fn id(&self) -> usize {
         self.id
     }
@@ -36,69 +121,283 @@ impl Image {
         (self.w, self.h)
     }
 
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn import_settings(&self) -> &ImportSettings {
+        &self.settings
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.settings.color_space
+    }
+
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.settings.color_space = color_space;
+        self.changed();
+    }
+
+    pub fn texture_options(&self) -> TextureOptions {
+        self.settings.texture
+    }
+
+    pub fn set_texture_options(&mut self, options: TextureOptions) {
+        self.settings.texture = options;
+        // Drops the cached texture with the old options
+        self.changed();
+    }
+
     pub fn data(&self) -> &[u8] {
         &self.data
     }
+
+    /// The pixel at column `x` and row `y` of any format, decoded like
+    /// `PixelFormat::read` but not converted from sRGB.
+    pub fn texel(&self, x: i32, y: i32) -> [f32; 4] {
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let i = self.pixel_index(x, y) * bytes_per_pixel;
+        self.format.read(&self.data[i..i + bytes_per_pixel])
+    }
+
+    /// Encodes `value` into the pixel at column `x` and row `y`, see `PixelFormat::write`.
+    pub fn set_texel(&mut self, x: i32, y: i32, value: [f32; 4]) {
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let i = self.pixel_index(x, y) * bytes_per_pixel;
+        self.format.write(value, &mut self.data[i..i + bytes_per_pixel]);
+        self.changed();
+    }
+
+    /// The pixel at column `x` and row `y` as 8 bit RGBA, counted from the
+    /// bottom like `Frame` regions. Other formats are converted like `texel`,
+    /// with floats clamped to 0..1.
+    pub fn pixel(&self, x: i32, y: i32) -> [u8; 4] {
+        if self.format != PixelFormat::Rgba8 {
+            let mut pixel = [0; 4];
+            PixelFormat::Rgba8.write(self.texel(x, y), &mut pixel);
+            return pixel;
+        }
+
+        let i = self.pixel_index(x, y) * 4;
+        [self.data[i], self.data[i + 1], self.data[i + 2], self.data[i + 3]]
+    }
+
+    /// Other formats keep the channels they have, e.g. only red for `R8`.
+    pub fn set_pixel(&mut self, x: i32, y: i32, pixel: [u8; 4]) {
+        if self.format != PixelFormat::Rgba8 {
+            self.set_texel(x, y, PixelFormat::Rgba8.read(&pixel));
+            return;
+        }
+
+        let i = self.pixel_index(x, y) * 4;
+        self.data[i..i + 4].copy_from_slice(&pixel);
+        self.changed();
+    }
+
+    /// A copy of the `w`x`h` pixels whose bottom left corner is at `x`, `y`.
+    pub fn crop(&self, x: i32, y: i32, w: i32, h: i32) -> Image {
+        assert!(x >= 0 && y >= 0 && w >= 0 && h >= 0 && x + w <= self.w && y + h <= self.h,
+                "Crop {}x{} at ({}, {}) is outside the {}x{} image", w, h, x, y, self.w, self.h);
+
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let row_len = w as usize * bytes_per_pixel;
+        let mut data = Vec::with_capacity(row_len * h as usize);
+        for row in y..y + h {
+            let start = ((row * self.w + x) as usize) * bytes_per_pixel;
+            data.extend_from_slice(&self.data[start..start + row_len]);
+        }
+        Image::from_pixels(w, h, self.format, data, self.settings)
+    }
+
+    /// Draws `src` over this image with its bottom left corner at `x`, `y`.
+    /// Both images are pre-multiplied, the parts outside are clipped. Images
+    /// of different formats are blended like their `texel`s.
+    pub fn blit(&mut self, src: &Image, x: i32, y: i32) {
+        let x0 = cmp::max(x, 0);
+        let y0 = cmp::max(y, 0);
+        let x1 = cmp::min(x + src.w, self.w);
+        let y1 = cmp::min(y + src.h, self.h);
+
+        let is_rgba8 = self.format == PixelFormat::Rgba8 && src.format == PixelFormat::Rgba8;
+
+        for dy in y0..y1 {
+            for dx in x0..x1 {
+                if !is_rgba8 {
+                    let s = src.texel(dx - x, dy - y);
+                    let mut d = self.texel(dx, dy);
+                    for c in 0..4 {
+                        d[c] = s[c] + d[c] * (1.0 - s[3]);
+                    }
+                    let bytes_per_pixel = self.format.bytes_per_pixel();
+                    let i = self.pixel_index(dx, dy) * bytes_per_pixel;
+                    self.format.write(d, &mut self.data[i..i + bytes_per_pixel]);
+                    continue;
+                }
+
+                let s = src.pixel(dx - x, dy - y);
+                let i = self.pixel_index(dx, dy) * 4;
+                let inv_a = 255 - s[3] as u32;
+                for c in 0..4 {
+                    let d = self.data[i + c] as u32;
+                    self.data[i + c] = cmp::min(s[c] as u32 + (d * inv_a + 127) / 255, 255) as u8;
+                }
+            }
+        }
+        self.changed();
+    }
+
+    /// A copy scaled to `w`x`h` in the same format.
+    pub fn resize(&self, w: i32, h: i32, filter: ResizeFilter) -> Image {
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let mut data = vec![0; w as usize * h as usize * bytes_per_pixel];
+        if self.w <= 0 || self.h <= 0 {
+            return Image::from_pixels(w, h, self.format, data, self.settings);
+        }
+
+        let scale_x = self.w as f32 / w as f32;
+        let scale_y = self.h as f32 / h as f32;
+
+        for y in 0..h {
+            for x in 0..w {
+                // Position of the new pixel's center in the old image
+                let sx = (x as f32 + 0.5) * scale_x;
+                let sy = (y as f32 + 0.5) * scale_y;
+
+                let i = (y * w + x) as usize * bytes_per_pixel;
+                let out = &mut data[i..i + bytes_per_pixel];
+                match filter {
+                    ResizeFilter::Nearest => {
+                        let j = self.pixel_index(clamp(sx as i32, 0, self.w - 1), clamp(sy as i32, 0, self.h - 1)) * bytes_per_pixel;
+                        out.copy_from_slice(&self.data[j..j + bytes_per_pixel]);
+                    }
+                    ResizeFilter::Bilinear if self.format == PixelFormat::Rgba8 => {
+                        out.copy_from_slice(&self.bilinear(sx - 0.5, sy - 0.5));
+                    }
+                    ResizeFilter::Bilinear => self.format.write(self.bilinear_texel(sx - 0.5, sy - 0.5), out),
+                }
+            }
+        }
+
+        Image::from_pixels(w, h, self.format, data, self.settings)
+    }
+
+    /// A copy in `Rgba8`, converted like `pixel`.
+    pub fn to_rgba8(&self) -> Image {
+        if self.format == PixelFormat::Rgba8 {
+            return Image::from_pixels(self.w, self.h, self.format, self.data.clone(), self.settings);
+        }
+
+        let mut data = Vec::with_capacity(self.w as usize * self.h as usize * 4);
+        for y in 0..self.h {
+            for x in 0..self.w {
+                data.extend_from_slice(&self.pixel(x, y));
+            }
+        }
+        Image::from_pixels(self.w, self.h, PixelFormat::Rgba8, data, self.settings)
+    }
+
+    /// Straight alpha RGBA rows from top to bottom, the import reverted.
+    /// Other formats are converted with `to_rgba8` first.
+    pub fn to_file_rgba(&self) -> Vec<u8> {
+        if self.format != PixelFormat::Rgba8 {
+            return self.to_rgba8().to_file_rgba();
+        }
+        self.settings.revert(self.w, self.h, &self.data)
+    }
+
+    /// Writes the image as PNG, e.g. for screenshots or generated textures.
+    /// Fails for empty images.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        if self.w <= 0 || self.h <= 0 {
+            return Err(Error::InvalidInput(format!("Can't save the empty {}x{} image as PNG", self.w, self.h)));
+        }
+
+        try!(png::write_rgba(path, self.w as u32, self.h as u32, &self.to_file_rgba()));
+        Ok(())
+    }
+
+    fn bilinear(&self, x: f32, y: f32) -> [u8; 4] {
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let corner = |cx: i32, cy: i32| self.pixel(clamp(cx, 0, self.w - 1), clamp(cy, 0, self.h - 1));
+        let (p00, p10, p01, p11) = (corner(x0, y0), corner(x0 + 1, y0), corner(x0, y0 + 1), corner(x0 + 1, y0 + 1));
+
+        let mut pixel = [0; 4];
+        for c in 0..4 {
+            let bottom = p00[c] as f32 * (1.0 - fx) + p10[c] as f32 * fx;
+            let top = p01[c] as f32 * (1.0 - fx) + p11[c] as f32 * fx;
+            pixel[c] = (bottom * (1.0 - fy) + top * fy + 0.5) as u8;
+        }
+        pixel
+    }
+
+    // `bilinear` for any format, without rounding to 8 bits
+    fn bilinear_texel(&self, x: f32, y: f32) -> [f32; 4] {
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let corner = |cx: i32, cy: i32| self.texel(clamp(cx, 0, self.w - 1), clamp(cy, 0, self.h - 1));
+        let (p00, p10, p01, p11) = (corner(x0, y0), corner(x0 + 1, y0), corner(x0, y0 + 1), corner(x0 + 1, y0 + 1));
+
+        let mut value = [0.0; 4];
+        for c in 0..4 {
+            let bottom = p00[c] * (1.0 - fx) + p10[c] * fx;
+            let top = p01[c] * (1.0 - fx) + p11[c] * fx;
+            value[c] = bottom * (1.0 - fy) + top * fy;
+        }
+        value
+    }
+
+    fn pixel_index(&self, x: i32, y: i32) -> usize {
+        assert!(x >= 0 && y >= 0 && x < self.w && y < self.h,
+                "Pixel ({}, {}) is outside the {}x{} image", x, y, self.w, self.h);
+        (y * self.w + x) as usize
+    }
+
+    fn changed(&mut self) {
+        self.id = COUNTER.next();
+    }
+}
+
+// `floats` has `format.channels()` values per pixel.
+fn floats_to_pixels(format: PixelFormat, floats: &[f32]) -> Vec<u8> {
+    let channels = format.channels();
+    let bytes_per_pixel = format.bytes_per_pixel();
+    let mut pixels = vec![0; floats.len() / channels * bytes_per_pixel];
+    for (pixel, out) in floats.chunks(channels).zip(pixels.chunks_mut(bytes_per_pixel)) {
+        let mut value = [0.0, 0.0, 0.0, 1.0];
+        value[..channels].copy_from_slice(pixel);
+        format.write(value, out);
+    }
+    pixels
+}
+
+fn clamp(v: i32, min: i32, max: i32) -> i32 {
+    cmp::max(min, cmp::min(v, max))
 }
 
 impl Asset for Image {
     fn name() -> &'static str {
         "Image"
     }
+
+    fn memory_usage(&self) -> usize {
+        self.data.len()
+    }
 }
 
+/// Uses the settings of the `.import` sidecar next to the file, if any.
 impl Loadable for Image {
-    fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        let path = path.as_ref();
-        unsafe {
-            let cstr = CString::new(&*path.as_os_str().to_string_lossy()).unwrap();
-            let mut w = 0;
-            let mut h = 0;
-            let data = stbi_load(cstr.as_ptr(), &mut w, &mut h, ptr::null_mut(), 4);
-            if data != ptr::null_mut() {
-                let mut pixels = Vec::with_capacity((w * 4 * h) as usize);
-                // Flip the pixels
-                {
-                    let data = ::std::slice::from_raw_parts(data, (w * 4 * h) as usize);
-                    for row in data.chunks((w * 4) as usize).rev() {
-                        for pixel in row.chunks(4) {
-                            let mut r = pixel[0] as f32 / 255.0;
-                            let mut g = pixel[1] as f32 / 255.0;
-                            let mut b = pixel[2] as f32 / 255.0;
-                            let a = pixel[3] as f32 / 255.0;
-
-                            let gamma = 2.1;
-
-                            r = r.powf(gamma);
-                            g = g.powf(gamma);
-                            b = b.powf(gamma);
-
-                            r = r * a;
-                            g = g * a;
-                            b = b * a;
-
-                            r = r.powf(1.0 / gamma);
-                            g = g.powf(1.0 / gamma);
-                            b = b.powf(1.0 / gamma);
-
-                            pixels.push((r * 255.0) as u8);
-                            pixels.push((g * 255.0) as u8);
-                            pixels.push((b * 255.0) as u8);
-                            pixels.push(pixel[3]);
-                        }
-                    }
-                }
-                stbi_image_free(data);
-                Ok(Image {
-                    id: COUNTER.next(),
-                    w: w,
-                    h: h,
-                    data: pixels,
-                })
-            } else {
-                Err(format!("Failed to load {}: {}", path.display(), cstr_to_string(stbi_failure_reason())).into())
-            }
-        }
+    fn load<R: Read>(reader: &mut R, path: &Path) -> Result<Self, Error> {
+        let settings = try!(ImportSettings::for_image(path));
+        Image::load_with(reader, path, &settings)
     }
 }
 
@@ -107,6 +406,7 @@ pub struct Frame {
     image: ImageRef,
     region: Rect,
     anchor: Vector,
+    texture_options: Option<TextureOptions>,
 }
 
 impl Frame {
@@ -115,6 +415,7 @@ impl Frame {
             image: image,
             region: region,
             anchor: Vector::zero(),
+            texture_options: None,
         }
     }
 
@@ -137,6 +438,16 @@ impl Frame {
     pub fn set_anchor(&mut self, anchor: Vector) {
         self.anchor = anchor;
     }
+
+    /// The options of this frame, or of its image if it has none.
+    pub fn texture_options(&self) -> TextureOptions {
+        self.texture_options.unwrap_or_else(|| self.image.read().texture_options())
+    }
+
+    /// Samples this frame differently than the rest of its image, e.g. to tile it.
+    pub fn set_texture_options(&mut self, options: Option<TextureOptions>) {
+        self.texture_options = options;
+    }
 }
 
 impl Asset for Frame {
@@ -0,0 +1,330 @@
+//! How the pixels of an image file are converted when it is loaded.
+//!
+//! The settings come from `asset::<Image>::load_with`, or from a JSON sidecar
+//! next to the image, e.g. `hero.png.import`:
+//!
+//! ```json
+//! { "color_space": "linear", "premultiply": false, "flip": true, "gamma": "srgb", "format": "r8",
+//!   "filter": "trilinear", "wrap": "repeat", "mipmaps": true, "anisotropy": 8 }
+//! ```
+//!
+//! Missing fields keep their default.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde_json::{self, Value};
+
+use Error;
+
+use super::PixelFormat;
+
+use util::color::{srgb_to_linear, linear_to_srgb};
+use vfs;
+
+/// What the color channels of an image contain.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorSpace {
+    /// Gamma encoded colors, decoded by the texture sampler. Art is usually this.
+    Srgb,
+    /// Values used as they are, e.g. normal maps or lookup tables.
+    Linear,
+}
+
+/// The curve used to decode `Srgb` colors before the alpha is multiplied in,
+/// by default the one the texture sampler uses.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Gamma {
+    Power(f32),
+    /// The exact piecewise sRGB curve.
+    Srgb,
+}
+
+impl Gamma {
+    pub fn decode(self, c: f32) -> f32 {
+        match self {
+            Gamma::Power(gamma) => c.powf(gamma),
+            Gamma::Srgb => srgb_to_linear(c),
+        }
+    }
+
+    pub fn encode(self, c: f32) -> f32 {
+        match self {
+            Gamma::Power(gamma) => c.powf(1.0 / gamma),
+            Gamma::Srgb => linear_to_srgb(c),
+        }
+    }
+}
+
+/// How a texture is sampled between and beyond its texels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    /// Sharp texels, for pixel art.
+    Nearest,
+    Linear,
+    /// Linear between texels and between mipmaps, implies mipmaps.
+    Trilinear,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Wrap {
+    /// Repeats the edge texels.
+    Clamp,
+    Repeat,
+    /// Repeats the texture flipped every other time.
+    Mirror,
+}
+
+/// Sampling state of the texture uploaded for an image. A `Frame` can
+/// override the options of its image, each combination gets its own texture.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureOptions {
+    pub filter: Filter,
+    pub wrap: Wrap,
+    pub mipmaps: bool,
+    /// Maximum anisotropic filtering, 1 turns it off.
+    pub anisotropy: u32,
+}
+
+impl Default for TextureOptions {
+    fn default() -> TextureOptions {
+        TextureOptions {
+            filter: Filter::Nearest,
+            wrap: Wrap::Clamp,
+            mipmaps: false,
+            anisotropy: 1,
+        }
+    }
+}
+
+impl TextureOptions {
+    /// Options for scaled HD art.
+    pub fn smooth() -> TextureOptions {
+        TextureOptions {
+            filter: Filter::Trilinear,
+            mipmaps: true,
+            ..TextureOptions::default()
+        }
+    }
+
+    pub fn has_mipmaps(&self) -> bool {
+        self.mipmaps || self.filter == Filter::Trilinear
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImportSettings {
+    pub color_space: ColorSpace,
+    /// Multiply the colors by alpha, which the renderer's blending expects.
+    pub premultiply: bool,
+    /// Store the rows from bottom to top, the way the renderer samples them.
+    pub flip: bool,
+    pub gamma: Gamma,
+    /// `None` picks `Rgba16F` for HDR files and `Rgba8` for everything else.
+    pub format: Option<PixelFormat>,
+    pub texture: TextureOptions,
+}
+
+impl Default for ImportSettings {
+    fn default() -> ImportSettings {
+        ImportSettings {
+            color_space: ColorSpace::Srgb,
+            premultiply: true,
+            flip: true,
+            gamma: Gamma::Srgb,
+            format: None,
+            texture: TextureOptions::default(),
+        }
+    }
+}
+
+impl ImportSettings {
+    /// Settings for images which hold data instead of colors.
+    pub fn data() -> ImportSettings {
+        ImportSettings {
+            color_space: ColorSpace::Linear,
+            premultiply: false,
+            ..ImportSettings::default()
+        }
+    }
+
+    /// The path of the sidecar of the image at `path`.
+    pub fn sidecar_path<P: AsRef<Path>>(path: P) -> PathBuf {
+        PathBuf::from(format!("{}.import", path.as_ref().display()))
+    }
+
+    /// Reads the sidecar of the image at `path`, the defaults if there is none.
+    pub fn for_image<P: AsRef<Path>>(path: P) -> Result<ImportSettings, Error> {
+        let sidecar = ImportSettings::sidecar_path(path);
+        if !vfs::exists(&sidecar) {
+            return Ok(ImportSettings::default());
+        }
+
+        let mut json = String::new();
+        try!(try!(vfs::open(&sidecar)).read_to_string(&mut json));
+        ImportSettings::parse(&json).map_err(|e| Error::Decode(format!("{}: {}", sidecar.display(), e)))
+    }
+
+    pub fn parse(json: &str) -> Result<ImportSettings, Error> {
+        let value: Value = try!(serde_json::from_str(json));
+        let mut settings = ImportSettings::default();
+
+        if let Some(color_space) = value.find("color_space") {
+            settings.color_space = match color_space.as_str() {
+                Some("srgb") => ColorSpace::Srgb,
+                Some("linear") => ColorSpace::Linear,
+                _ => return Err(Error::Decode("`color_space` must be \"srgb\" or \"linear\"".to_string())),
+            };
+        }
+
+        if let Some(premultiply) = value.find("premultiply") {
+            settings.premultiply = try!(premultiply.as_bool().ok_or_else(|| Error::Decode("`premultiply` is not a bool".to_string())));
+        }
+
+        if let Some(flip) = value.find("flip") {
+            settings.flip = try!(flip.as_bool().ok_or_else(|| Error::Decode("`flip` is not a bool".to_string())));
+        }
+
+        if let Some(gamma) = value.find("gamma") {
+            settings.gamma = match (gamma.as_str(), gamma.as_f64()) {
+                (Some("srgb"), _) => Gamma::Srgb,
+                (_, Some(power)) if power > 0.0 => Gamma::Power(power as f32),
+                _ => return Err(Error::Decode("`gamma` must be \"srgb\" or a positive number".to_string())),
+            };
+        }
+
+        if let Some(format) = value.find("format") {
+            settings.format = match format.as_str().and_then(PixelFormat::parse) {
+                Some(format) => Some(format),
+                None => return Err(Error::Decode("`format` must be one of \"r8\", \"rg8\", \"rgb8\", \"rgba8\", \"rgba16f\" or \"r32f\"".to_string())),
+            };
+        }
+
+        if let Some(filter) = value.find("filter") {
+            settings.texture.filter = match filter.as_str() {
+                Some("nearest") => Filter::Nearest,
+                Some("linear") => Filter::Linear,
+                Some("trilinear") => Filter::Trilinear,
+                _ => return Err(Error::Decode("`filter` must be \"nearest\", \"linear\" or \"trilinear\"".to_string())),
+            };
+        }
+
+        if let Some(wrap) = value.find("wrap") {
+            settings.texture.wrap = match wrap.as_str() {
+                Some("clamp") => Wrap::Clamp,
+                Some("repeat") => Wrap::Repeat,
+                Some("mirror") => Wrap::Mirror,
+                _ => return Err(Error::Decode("`wrap` must be \"clamp\", \"repeat\" or \"mirror\"".to_string())),
+            };
+        }
+
+        if let Some(mipmaps) = value.find("mipmaps") {
+            settings.texture.mipmaps = try!(mipmaps.as_bool().ok_or_else(|| Error::Decode("`mipmaps` is not a bool".to_string())));
+        }
+
+        if let Some(anisotropy) = value.find("anisotropy") {
+            settings.texture.anisotropy = match anisotropy.as_u64() {
+                Some(anisotropy) if anisotropy >= 1 && anisotropy <= 16 => anisotropy as u32,
+                _ => return Err(Error::Decode("`anisotropy` must be a number from 1 to 16".to_string())),
+            };
+        }
+
+        Ok(settings)
+    }
+
+    /// Converts tightly packed rows from top to bottom, as decoded from the
+    /// file. Only formats with alpha are pre-multiplied, floats in linear space.
+    pub fn apply<'a>(&self, w: i32, h: i32, format: PixelFormat, data: &'a [u8]) -> Vec<u8> {
+        // There are no rows to split an empty image into
+        if w <= 0 || h <= 0 {
+            return Vec::new();
+        }
+
+        let bytes_per_pixel = format.bytes_per_pixel();
+        let row_len = w as usize * bytes_per_pixel;
+        let mut pixels = Vec::with_capacity(row_len * h as usize);
+
+        let rows: Box<Iterator<Item = &'a [u8]> + 'a> = if self.flip {
+            Box::new(data.chunks(row_len).rev())
+        } else {
+            Box::new(data.chunks(row_len))
+        };
+
+        for row in rows {
+            if !self.premultiply || !format.has_alpha() {
+                pixels.extend_from_slice(row);
+                continue;
+            }
+
+            if format.is_float() {
+                for pixel in row.chunks(bytes_per_pixel) {
+                    let mut value = format.read(pixel);
+                    for c in 0..3 {
+                        value[c] *= value[3];
+                    }
+                    let start = pixels.len();
+                    pixels.resize(start + bytes_per_pixel, 0);
+                    format.write(value, &mut pixels[start..]);
+                }
+                continue;
+            }
+
+            for pixel in row.chunks(4) {
+                let a = pixel[3] as f32 / 255.0;
+                for c in 0..3 {
+                    let value = pixel[c] as f32 / 255.0;
+                    let value = match self.color_space {
+                        ColorSpace::Srgb => self.gamma.encode(self.gamma.decode(value) * a),
+                        ColorSpace::Linear => value * a,
+                    };
+                    pixels.push((value * 255.0) as u8);
+                }
+                pixels.push(pixel[3]);
+            }
+        }
+
+        pixels
+    }
+
+    /// The inverse of `apply` for `Rgba8`, straight alpha rows from top to bottom.
+    pub fn revert<'a>(&self, w: i32, h: i32, data: &'a [u8]) -> Vec<u8> {
+        if w <= 0 || h <= 0 {
+            return Vec::new();
+        }
+
+        let row_len = (w * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_len * h as usize);
+
+        let rows: Box<Iterator<Item = &'a [u8]> + 'a> = if self.flip {
+            Box::new(data.chunks(row_len).rev())
+        } else {
+            Box::new(data.chunks(row_len))
+        };
+
+        for row in rows {
+            if !self.premultiply {
+                pixels.extend_from_slice(row);
+                continue;
+            }
+
+            for pixel in row.chunks(4) {
+                let a = pixel[3] as f32 / 255.0;
+                if a > 0.0 {
+                    for c in 0..3 {
+                        let value = pixel[c] as f32 / 255.0;
+                        let value = match self.color_space {
+                            ColorSpace::Srgb => self.gamma.encode(self.gamma.decode(value) / a),
+                            ColorSpace::Linear => value / a,
+                        };
+                        pixels.push(if value >= 1.0 { 255 } else { (value * 255.0) as u8 });
+                    }
+                } else {
+                    pixels.extend_from_slice(&[0, 0, 0]);
+                }
+                pixels.push(pixel[3]);
+            }
+        }
+
+        pixels
+    }
+}
@@ -0,0 +1,212 @@
+//! Worker threads for `asset::<A>::load_async`.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender, Receiver, TryRecvError};
+use std::thread;
+
+use super::{Asset, AssetRef};
+
+use Error;
+
+use math::Scalar;
+
+const WORKER_COUNT: usize = 4;
+
+trait Job: Send {
+    fn run(self: Box<Self>);
+}
+
+impl<F: FnOnce() + Send> Job for F {
+    fn run(self: Box<Self>) {
+        let f = *self;
+        f()
+    }
+}
+
+struct Pool {
+    sender: Mutex<Sender<Box<Job>>>,
+}
+
+impl Pool {
+    fn new(count: usize) -> Pool {
+        let (sender, receiver) = mpsc::channel::<Box<Job>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..count {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("hammer-loader-{}", i))
+                .spawn(move || {
+                    loop {
+                        // The lock is released before running the job, so the other workers can pick up the next one
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job.run(),
+                            Err(_) => break,
+                        }
+                    }
+                })
+                .expect("Failed to spawn asset loader thread");
+        }
+
+        Pool {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    fn execute<F: FnOnce() + Send + 'static>(&self, f: F) {
+        self.sender.lock().unwrap().send(Box::new(f)).expect("Asset loader threads are gone");
+    }
+}
+
+lazy_static! {
+    static ref POOL: Pool = Pool::new(WORKER_COUNT);
+
+    static ref PENDING: AtomicUsize = AtomicUsize::new(0);
+    static ref LOADED: AtomicUsize = AtomicUsize::new(0);
+    static ref FAILED: AtomicUsize = AtomicUsize::new(0);
+}
+
+/// Counts of the `load_async` requests since start or the last `reset_progress`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Progress {
+    pub pending: usize,
+    pub loaded: usize,
+    pub failed: usize,
+}
+
+impl Progress {
+    pub fn total(&self) -> usize {
+        self.pending + self.loaded + self.failed
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending == 0
+    }
+
+    /// Finished part of the requests in [0, 1], for loading bars.
+    pub fn ratio(&self) -> Scalar {
+        match self.total() {
+            0 => 1.0,
+            total => (self.loaded + self.failed) as Scalar / total as Scalar,
+        }
+    }
+}
+
+pub fn progress() -> Progress {
+    Progress {
+        pending: PENDING.load(Ordering::SeqCst),
+        loaded: LOADED.load(Ordering::SeqCst),
+        failed: FAILED.load(Ordering::SeqCst),
+    }
+}
+
+/// Forgets the finished requests, e.g. before the next loading screen.
+pub fn reset_progress() {
+    LOADED.store(0, Ordering::SeqCst);
+    FAILED.store(0, Ordering::SeqCst);
+}
+
+pub struct LoadHandle<A: Asset> {
+    receiver: Receiver<Result<AssetRef<A>, Error>>,
+    result: Option<Result<AssetRef<A>, Error>>,
+}
+
+impl<A: Asset> LoadHandle<A> {
+    /// Returns the result once the load finished, without blocking.
+    pub fn poll(&mut self) -> Option<&Result<AssetRef<A>, Error>> {
+        if self.result.is_none() {
+            match self.receiver.try_recv() {
+                Ok(result) => self.result = Some(result),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.result = Some(Err(Error::Panicked("Asset loader thread is gone".to_string()))),
+            }
+        }
+        self.result.as_ref()
+    }
+
+    pub fn is_done(&mut self) -> bool {
+        self.poll().is_some()
+    }
+
+    /// Blocks until the load finished.
+    pub fn wait(self) -> Result<AssetRef<A>, Error> {
+        match self.result {
+            Some(result) => result,
+            None => self.receiver.recv().unwrap_or_else(|_| Err(Error::Panicked("Asset loader thread is gone".to_string()))),
+        }
+    }
+}
+
+/// Runs `f`, a panic becomes `Error::Panicked` instead of unwinding further.
+pub fn catch_panic<T, F: FnOnce() -> Result<T, Error>>(f: F) -> Result<T, Error> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => Err(Error::Panicked(panic_message(&*payload))),
+    }
+}
+
+fn panic_message(payload: &(Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "Unknown panic".to_string(),
+    }
+}
+
+pub fn spawn<A, F>(load: F) -> LoadHandle<A>
+    where A: Asset,
+          F: FnOnce() -> Result<AssetRef<A>, Error> + Send + 'static
+{
+    let (sender, receiver) = mpsc::channel();
+
+    PENDING.fetch_add(1, Ordering::SeqCst);
+    POOL.execute(move || {
+        // A panic would kill the worker and leave the request pending forever
+        let result = catch_panic(load);
+        match result {
+            Ok(_) => LOADED.fetch_add(1, Ordering::SeqCst),
+            Err(ref e) => {
+                error!("{}", e);
+                FAILED.fetch_add(1, Ordering::SeqCst)
+            }
+        };
+        PENDING.fetch_sub(1, Ordering::SeqCst);
+
+        // Nobody cares about the result if the handle was dropped
+        let _ = sender.send(result);
+    });
+
+    LoadHandle {
+        receiver: receiver,
+        result: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use asset::{Image, ImageRef};
+
+    use Error;
+
+    use super::{progress, spawn, WORKER_COUNT};
+
+    #[test]
+    fn panicking_load_fails() {
+        // More panics than workers, the pool has to survive them all
+        let handles = (0..WORKER_COUNT * 2).map(|_| {
+            spawn(|| -> Result<ImageRef, Error> { panic!("Corrupt image") })
+        }).collect::<Vec<_>>();
+
+        for handle in handles {
+            assert_eq!(handle.wait().err(), Some(Error::Panicked("Corrupt image".to_string())));
+        }
+
+        let image = spawn(|| Ok(ImageRef::new(Image::new(1, 1)))).wait();
+        assert!(image.is_ok());
+        assert!(progress().is_done());
+    }
+}
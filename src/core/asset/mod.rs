@@ -1,26 +1,56 @@
-use std::any::Any;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::fmt;
-use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::{Arc, Condvar, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::io::Read;
 use std::path::Path;
 
 use Error;
 
+use vfs;
+
 use typemap::{TypeMap, Key};
 
-pub use self::image::{Image, ImageRef, Frame, FrameRef};
-pub use self::animation::{Animation, AnimationRef, WrapMode};
+pub use self::image::{Image, ImageRef, WeakImageRef, Frame, FrameRef, ResizeFilter};
+pub use self::animation::{Animation, AnimationRef, AnimationPlayer, WrapMode};
+pub use self::atlas::{Atlas, AtlasBuilder};
+pub use self::import::{ImportSettings, ColorSpace, Gamma, TextureOptions, Filter, Wrap};
+pub use self::format::PixelFormat;
+pub use self::loader::{LoadHandle, Progress, progress, reset_progress};
+pub use self::watch::{enable_hot_reload, reload_changed};
 
 pub mod image;
 pub mod animation;
+pub mod atlas;
+pub mod import;
+pub mod format;
+
+mod loader;
+mod sheet;
+mod watch;
 
 pub trait Asset: Any + Send + Sync {
     fn name() -> &'static str;
+
+    /// Bytes held by the asset, counted against the budget of its type.
+    fn memory_usage(&self) -> usize {
+        0
+    }
 }
 
 pub trait Loadable: Sized {
-    fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error>;
+    /// Decodes the asset from `reader`, which has the content of the file at
+    /// `path`. Files it refers to should be resolved relative to `path`.
+    fn load<R: Read>(reader: &mut R, path: &Path) -> Result<Self, Error>;
+}
+
+// Opens `path` through the `vfs`, so assets can come from mounted archives.
+fn load_from_vfs<A: Loadable>(path: &Path) -> Result<A, Error> {
+    let mut reader = try!(vfs::open(path));
+    A::load(&mut reader, path)
 }
 
 pub struct AssetRef<A: Asset> {
@@ -56,23 +86,75 @@ impl<A: Asset> AssetRef<A> {
     pub fn write(&self) -> RwLockWriteGuard<A> {
         self.asset.write().unwrap()
     }
+
+    /// Number of `AssetRef`s to this asset, including the registry's own.
+    pub fn strong_count(&self) -> usize {
+        Arc::strong_count(&self.asset)
+    }
+
+    /// A reference which doesn't keep the asset alive.
+    pub fn downgrade(&self) -> WeakAssetRef<A> {
+        WeakAssetRef {
+            asset: Arc::downgrade(&self.asset),
+        }
+    }
+}
+
+pub struct WeakAssetRef<A: Asset> {
+    asset: Weak<RwLock<A>>,
+}
+
+impl<A: Asset> Clone for WeakAssetRef<A> {
+    fn clone(&self) -> WeakAssetRef<A> {
+        WeakAssetRef {
+            asset: self.asset.clone()
+        }
+    }
+}
+
+impl<A: Asset> WeakAssetRef<A> {
+    pub fn upgrade(&self) -> Option<AssetRef<A>> {
+        self.asset.upgrade().map(|asset| AssetRef { asset: asset })
+    }
 }
 
 struct Slot<A: Asset> {
     id: String,
-    asset: Arc<RwLock<SlotState<A>>>,
+    // The condition variable is notified whenever the state leaves `Loading`.
+    asset: Arc<(Mutex<SlotState<A>>, Condvar)>,
+    // Value of `TICK` when the asset was last requested, for LRU eviction.
+    last_used: Arc<AtomicUsize>,
 }
 
 impl<A: Asset> Slot<A> {
     fn new(id: String) -> Slot<A> {
         Slot {
             id: id,
-            asset: Arc::new(RwLock::new(SlotState::Unloaded)),
+            asset: Arc::new((Mutex::new(SlotState::Unloaded), Condvar::new())),
+            last_used: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn touch(&self) {
+        self.last_used.store(TICK.fetch_add(1, Ordering::SeqCst), Ordering::SeqCst);
+    }
+
+    fn is_same(&self, other: &Slot<A>) -> bool {
+        &*self.asset as *const _ == &*other.asset as *const _
+    }
+
+    /// A reference which doesn't keep the slot alive once the registry dropped it.
+    fn downgrade(&self) -> WeakSlot<A> {
+        WeakSlot {
+            id: self.id.clone(),
+            asset: Arc::downgrade(&self.asset),
+            last_used: self.last_used.clone(),
         }
     }
 
     pub fn get(&self) -> Option<AssetRef<A>> {
-        let asset = self.asset.read().unwrap();
+        self.touch();
+        let asset = self.asset.0.lock().unwrap();
         match *asset {
             SlotState::Loaded(ref asset) => {
                 Some(asset.clone())
@@ -84,9 +166,10 @@ impl<A: Asset> Slot<A> {
 
     pub fn set(&self, asset: A) -> Option<AssetRef<A>> {
         let mut new_asset = SlotState::Loaded(AssetRef::new(asset));
-        let mut asset = self.asset.write().unwrap();
+        let mut asset = self.asset.0.lock().unwrap();
 
         ::std::mem::swap(&mut *asset, &mut new_asset);
+        self.asset.1.notify_all();
 
         let old_asset = new_asset;
         match old_asset {
@@ -95,9 +178,44 @@ impl<A: Asset> Slot<A> {
         }
     }
 
+    /// Drops the registry's reference, holders of an `AssetRef` keep their copy.
+    pub fn unload(&self) {
+        let mut asset = self.asset.0.lock().unwrap();
+        match *asset {
+            SlotState::Loading => {}
+            _ => {
+                *asset = SlotState::Unloaded;
+                self.asset.1.notify_all();
+            }
+        }
+    }
+
+    /// True if nothing but the registry refers to the asset, or it failed to load.
+    pub fn is_unused(&self) -> bool {
+        match *self.asset.0.lock().unwrap() {
+            SlotState::Loaded(ref asset) => asset.strong_count() == 1,
+            SlotState::LoadError(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        match *self.asset.0.lock().unwrap() {
+            SlotState::Loaded(ref asset) => asset.read().memory_usage(),
+            _ => 0,
+        }
+    }
+
+    pub fn error(&self) -> Option<Error> {
+        match *self.asset.0.lock().unwrap() {
+            SlotState::LoadError(ref e) => Some(e.clone()),
+            _ => None,
+        }
+    }
+
     /*
     pub fn loaded(&self) -> bool {
-        match *self.asset.read().unwrap() {
+        match *self.asset.0.lock().unwrap() {
             SlotState::Loaded(_) => true,
             _ => false,
         }
@@ -108,27 +226,60 @@ impl<A: Asset> Slot<A> {
 
 impl<A: Asset + Loadable> Slot<A> {
     pub fn load<P: AsRef<Path>>(self, path: P) -> Result<AssetRef<A>, Error> {
+        self.load_with(path, load_from_vfs::<A>)
+    }
+
+    /// Loads the asset with `load` unless it is already loaded. If another
+    /// thread is loading it, waits for that thread and shares its result.
+    /// Hot reloading calls `load` again.
+    pub fn load_with<P, F>(self, path: P, load: F) -> Result<AssetRef<A>, Error>
+        where P: AsRef<Path>,
+              F: Fn(&Path) -> Result<A, Error> + Send + Sync + 'static
+    {
+        self.touch();
+
         {
-            let mut asset = self.asset.write().unwrap();
-            match *asset {
-                SlotState::Loading => {
-                    return Err(format!("Asset {} is loading.", self).into());
-                }
+            let mut asset = self.asset.0.lock().unwrap();
+            let mut waited = false;
+            loop {
+                let is_loading = match *asset {
+                    // Waiting for ourselves would never end
+                    SlotState::Loading if self.is_loading_on_this_thread() => {
+                        return Err(Error::AlreadyLoading(self.to_string()));
+                    }
+
+                    SlotState::Loading => true,
 
-                SlotState::Loaded(ref asset) => {
-                    return Ok(asset.clone());
+                    SlotState::Loaded(ref asset) => {
+                        return Ok(asset.clone());
+                    }
+
+                    SlotState::LoadError(ref e) if waited => {
+                        return Err(e.clone());
+                    }
+
+                    _ => false,
+                };
+
+                if !is_loading {
+                    break;
                 }
 
-                _ => {}
+                asset = self.asset.1.wait(asset).unwrap();
+                waited = true;
             }
 
             *asset = SlotState::Loading;
         }
 
-        let result = A::load(path);
+        LOADING.with(|loading| loading.borrow_mut().push((TypeId::of::<A>(), self.id.clone())));
+        // Panics fail the slot, otherwise it would stay `Loading` and block its waiters
+        let result = loader::catch_panic(|| load(path.as_ref()));
+        LOADING.with(|loading| loading.borrow_mut().pop());
 
         {
-            let mut asset = self.asset.write().unwrap();
+            let mut asset = self.asset.0.lock().unwrap();
+            self.asset.1.notify_all();
 
             match *asset {
                 SlotState::Loading => {
@@ -137,21 +288,39 @@ impl<A: Asset + Loadable> Slot<A> {
                             let asset_ref = AssetRef { asset: Arc::new(RwLock::new(a)) };
                             *asset = SlotState::Loaded(asset_ref.clone());
                             info!("Loaded {}", self);
+                            watch::register(path.as_ref(), &self, load);
                             Ok(asset_ref)
                         }
 
                         Err(e) => {
-                            let err = Err(format!("{}", e).into());
-                            *asset = SlotState::LoadError(e);
-                            err
+                            *asset = SlotState::LoadError(e.clone());
+                            Err(e)
                         }
                     }
                 }
 
-                _ => Err("Loading interupted".into()),
+                _ => Err(Error::Interrupted(self.to_string())),
             }
         }
     }
+
+    fn is_loading_on_this_thread(&self) -> bool {
+        LOADING.with(|loading| loading.borrow().iter().any(|&(type_id, ref id)| type_id == TypeId::of::<A>() && *id == self.id))
+    }
+
+    /// Loads the asset again and replaces the content of its `AssetRef`.
+    fn reload<F: Fn(&Path) -> Result<A, Error>>(&self, path: &Path, load: &F) {
+        match load(path) {
+            Ok(a) => {
+                if let Some(asset_ref) = self.get() {
+                    *asset_ref.write() = a;
+                    info!("Reloaded {}", self);
+                }
+            }
+
+            Err(e) => error!("Failed to reload {}: {}", self, e),
+        }
+    }
 }
 
 impl<A: Asset> Clone for Slot<A> {
@@ -159,10 +328,27 @@ impl<A: Asset> Clone for Slot<A> {
         Slot {
             id: self.id.clone(),
             asset: self.asset.clone(),
+            last_used: self.last_used.clone(),
         }
     }
 }
 
+struct WeakSlot<A: Asset> {
+    id: String,
+    asset: Weak<(Mutex<SlotState<A>>, Condvar)>,
+    last_used: Arc<AtomicUsize>,
+}
+
+impl<A: Asset> WeakSlot<A> {
+    fn upgrade(&self) -> Option<Slot<A>> {
+        self.asset.upgrade().map(|asset| Slot {
+            id: self.id.clone(),
+            asset: asset,
+            last_used: self.last_used.clone(),
+        })
+    }
+}
+
 impl<A: Asset> fmt::Display for Slot<A> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}({})", A::name(), self.id)
@@ -174,10 +360,29 @@ pub struct asset<A: Asset> {
     phantom: PhantomData<A>,
 }
 
+/// The id under which the asset loaded from `path` is stored.
+pub fn path_to_id<P: AsRef<Path>>(path: P) -> String {
+    format!("{}", path.as_ref().display()).replace("\\", "/")
+}
+
 impl<A: Asset + Loadable> asset<A> {
     pub fn load<P: AsRef<Path>>(path: P) -> Result<AssetRef<A>, Error> {
-        let id = format!("{}", path.as_ref().display()).replace("\\", "/");
-        ASSETS.slots.acquire::<A>(&id).load(path)
+        let id = path_to_id(&path);
+        let result = ASSETS.slots.acquire::<A>(&id).load(path);
+        ASSETS.slots.enforce_budget::<A>();
+        result
+    }
+
+    /// Loads the asset on a worker thread, the returned handle can be polled
+    /// every frame or waited on. Counts towards `asset::progress`.
+    pub fn load_async<P: AsRef<Path>>(path: P) -> LoadHandle<A> {
+        let path = path.as_ref().to_path_buf();
+        let slot = ASSETS.slots.acquire::<A>(&path_to_id(&path));
+        loader::spawn(move || {
+            let result = slot.load(path);
+            ASSETS.slots.enforce_budget::<A>();
+            result
+        })
     }
 }
 
@@ -188,13 +393,105 @@ impl<A: Asset> asset<A> {
         slot.set(asset)
     }
 
+    /// Like `set`, but an asset already stored under `id` is replaced in
+    /// place, so the `AssetRef`s handed out before see the new one.
+    pub fn update<S: Into<String>>(id: S, asset: A) {
+        let id = id.into();
+        let slot = ASSETS.slots.acquire::<A>(&id);
+        match slot.get() {
+            Some(asset_ref) => *asset_ref.write() = asset,
+            None => {
+                slot.set(asset);
+            }
+        }
+    }
+
     pub fn get(id: &str) -> Option<AssetRef<A>> {
         ASSETS.slots.get::<A>(id).and_then(|slot| slot.get())
     }
+
+    /// Why the last load of the asset failed, `None` if it didn't.
+    pub fn error(id: &str) -> Option<Error> {
+        ASSETS.slots.get::<A>(id).and_then(|slot| slot.error())
+    }
+
+    /// Removes the asset from the registry, returns false if there was none.
+    /// Existing `AssetRef`s stay valid, the next `load` reads the file again.
+    pub fn unload(id: &str) -> bool {
+        match ASSETS.slots.remove::<A>(id) {
+            Some(slot) => {
+                slot.unload();
+                info!("Unloaded {}", slot);
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    /// Unloads the assets of this type nobody else refers to, returns how many.
+    pub fn collect() -> usize {
+        ASSETS.slots.collect::<A>()
+    }
+
+    /// Total `Asset::memory_usage` of the loaded assets of this type.
+    pub fn memory_usage() -> usize {
+        ASSETS.slots.all::<A>().iter().map(|slot| slot.memory_usage()).fold(0, |sum, usage| sum + usage)
+    }
+
+    /// Once the assets of this type use more than `budget` bytes, loading
+    /// another one unloads the least recently used unreferenced ones.
+    pub fn set_budget(budget: Option<usize>) {
+        {
+            let mut budgets = ASSETS.slots.budgets.lock().unwrap();
+            match budget {
+                Some(budget) => budgets.insert(TypeId::of::<A>(), budget),
+                None => budgets.remove(&TypeId::of::<A>()),
+            };
+        }
+        ASSETS.slots.enforce_budget::<A>();
+    }
+}
+
+// Above anything a game would mount itself.
+const PACK_PRIORITY: i32 = 1000;
+
+/// Resolves asset ids against the index of the pack at `path` before the file
+/// system, e.g. the `hammer-pack` output of the asset directory.
+pub fn mount_pack<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    let pack = try!(vfs::Pack::open(path));
+    vfs::mount("", pack, PACK_PRIORITY);
+    Ok(())
+}
+
+impl asset<Image> {
+    /// Loads the image with `settings` instead of the ones of its sidecar. An
+    /// image which is already loaded is returned as it is.
+    pub fn load_with<P: AsRef<Path>>(path: P, settings: ImportSettings) -> Result<ImageRef, Error> {
+        let id = path_to_id(&path);
+        let result = ASSETS.slots.acquire::<Image>(&id).load_with(path, move |path| {
+            let mut reader = try!(vfs::open(path));
+            Image::load_with(&mut reader, path, &settings)
+        });
+        ASSETS.slots.enforce_budget::<Image>();
+        result
+    }
+}
+
+/// Unloads the unreferenced assets of every type, returns how many.
+pub fn collect_garbage() -> usize {
+    let collectors = ASSETS.slots.collectors.lock().unwrap().clone();
+    collectors.iter().map(|collect| collect(&ASSETS.slots)).fold(0, |sum, count| sum + count)
+}
+
+thread_local! {
+    // Assets this thread is loading, innermost last.
+    static LOADING: RefCell<Vec<(TypeId, String)>> = RefCell::new(Vec::new());
 }
 
 lazy_static! {
     static ref ASSETS: Assets = Assets::new();
+    static ref TICK: AtomicUsize = AtomicUsize::new(0);
 }
 
 struct Assets {
@@ -211,23 +508,100 @@ impl Assets {
 
 struct Slots {
     slots: Mutex<TypeMap>,
+    budgets: Mutex<HashMap<TypeId, usize>>,
+    // `collect::<A>` of every type which has slots, for `collect_garbage`.
+    collectors: Mutex<Vec<fn(&Slots) -> usize>>,
 }
 
 impl Slots {
     fn new() -> Slots {
         Slots {
             slots: Mutex::new(TypeMap::new()),
+            budgets: Mutex::new(HashMap::new()),
+            collectors: Mutex::new(Vec::new()),
         }
     }
 
-    fn get<A: Asset>(&self, id: &str) -> Option<Slot<A>> {
+    fn all<A: Asset>(&self) -> Vec<Slot<A>> {
+        let type_slots = self.slots.lock().unwrap();
+        match type_slots.get::<AssetTypeMapKey<A>>() {
+            Some(slots) => slots.values().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn remove<A: Asset>(&self, id: &str) -> Option<Slot<A>> {
         let mut type_slots = self.slots.lock().unwrap();
-        let slots = type_slots.entry::<AssetTypeMapKey<A>>().or_insert_with(|| HashMap::new());
-        slots.get(id).cloned()
+        type_slots.get_mut::<AssetTypeMapKey<A>>().and_then(|slots| slots.remove(id))
+    }
+
+    // Removes `slot` unless it was replaced in the meantime.
+    fn remove_slot<A: Asset>(&self, slot: &Slot<A>) {
+        let mut type_slots = self.slots.lock().unwrap();
+        if let Some(slots) = type_slots.get_mut::<AssetTypeMapKey<A>>() {
+            if slots.get(&slot.id).map(|s| s.is_same(slot)).unwrap_or(false) {
+                slots.remove(&slot.id);
+            }
+        }
+        slot.unload();
+    }
+
+    fn collect<A: Asset>(&self) -> usize {
+        let mut count = 0;
+        for slot in self.all::<A>() {
+            if slot.is_unused() {
+                self.remove_slot(&slot);
+                count += 1;
+            }
+        }
+
+        if count > 0 {
+            info!("Collected {} unused {} assets", count, A::name());
+        }
+
+        count
+    }
+
+    fn enforce_budget<A: Asset>(&self) {
+        let budget = match self.budgets.lock().unwrap().get(&TypeId::of::<A>()) {
+            Some(&budget) => budget,
+            None => return,
+        };
+
+        let mut slots = self.all::<A>().into_iter().map(|slot| (slot.memory_usage(), slot)).collect::<Vec<_>>();
+        let mut usage = slots.iter().fold(0, |sum, &(size, _)| sum + size);
+        if usage <= budget {
+            return;
+        }
+
+        slots.sort_by_key(|&(_, ref slot)| slot.last_used.load(Ordering::SeqCst));
+        for (size, slot) in slots {
+            if usage <= budget {
+                break;
+            }
+
+            if size > 0 && slot.is_unused() {
+                self.remove_slot(&slot);
+                usage -= size;
+                info!("Evicted {} to stay within the budget", slot);
+            }
+        }
+
+        if usage > budget {
+            warn!("{} assets use {} bytes, the budget is {} bytes", A::name(), usage, budget);
+        }
+    }
+
+    fn get<A: Asset>(&self, id: &str) -> Option<Slot<A>> {
+        let type_slots = self.slots.lock().unwrap();
+        type_slots.get::<AssetTypeMapKey<A>>().and_then(|slots| slots.get(id).cloned())
     }
 
     fn acquire<A: Asset>(&self, id: &str) -> Slot<A> {
         let mut type_slots = self.slots.lock().unwrap();
+        if !type_slots.contains::<AssetTypeMapKey<A>>() {
+            self.collectors.lock().unwrap().push(Slots::collect::<A>);
+        }
         let slots = type_slots.entry::<AssetTypeMapKey<A>>().or_insert_with(|| HashMap::new());
 
         if let Some(asset) = slots.get(id) {
@@ -0,0 +1,140 @@
+//! Sprite sheets exported by Aseprite or TexturePacker as JSON.
+//!
+//! Both tools write `frames` either as a hash keyed by file name or as an
+//! array, and describe the sheet image in `meta`. Aseprite additionally
+//! writes a `duration` in milliseconds per frame and `meta.frameTags`.
+
+use std::io::Read;
+use std::path::Path;
+
+use serde_json::{self, Value};
+
+use super::*;
+
+use Error;
+
+use vfs;
+
+use math::{Rect, Scalar, Vector, vector};
+
+pub fn load_animation<R: Read>(reader: &mut R, path: &Path) -> Result<Animation, Error> {
+    let mut json = String::new();
+    try!(reader.read_to_string(&mut json));
+    let sheet: Value = try!(serde_json::from_str(&json));
+
+    let meta = try!(field(&sheet, "meta"));
+    let image_name = try!(try!(field(meta, "image")).as_str().ok_or_else(|| Error::Decode("`meta.image` is not a string".to_string())));
+    let image = try!(asset::<Image>::load(vfs::sibling(path, image_name)));
+    let (_, image_h) = image.read().size();
+
+    let frames = try!(field(&sheet, "frames"));
+    let entries = match (frames.as_object(), frames.as_array()) {
+        (Some(frames), _) => frames.values().collect::<Vec<_>>(),
+        (_, Some(frames)) => frames.iter().collect::<Vec<_>>(),
+        _ => return Err(Error::Decode("`frames` is neither an object nor an array".to_string())),
+    };
+
+    let mut frames = Vec::with_capacity(entries.len());
+    for entry in entries {
+        frames.push(try!(parse_frame(&image, image_h, entry)));
+    }
+
+    let mut animation = Animation::new();
+    for &(ref frame, duration) in frames.iter() {
+        add_frame(&mut animation, frame, duration);
+    }
+
+    if let Some(tags) = meta.find("frameTags").and_then(|tags| tags.as_array()) {
+        let id = path_to_id(path);
+        for tag in tags {
+            let name = try!(try!(field(tag, "name")).as_str().ok_or_else(|| Error::Decode("Frame tag name is not a string".to_string())));
+            let from = try!(int(tag, "from")) as usize;
+            let to = try!(int(tag, "to")) as usize;
+            if from > to || to >= frames.len() {
+                return Err(Error::Decode(format!("Frame tag {} is out of range", name)));
+            }
+
+            let mut tag_animation = Animation::new();
+
+            let mut indices = (from..to + 1).collect::<Vec<_>>();
+            match tag.find("direction").and_then(|direction| direction.as_str()) {
+                Some("reverse") => indices.reverse(),
+                Some("pingpong") => tag_animation.set_wrap_mode(WrapMode::PingPong),
+                _ => {}
+            }
+
+            for index in indices {
+                let (ref frame, duration) = frames[index];
+                add_frame(&mut tag_animation, frame, duration);
+            }
+
+            // Reloading the sheet updates the tags players already hold
+            asset::<Animation>::update(format!("{}#{}", id, name), tag_animation);
+        }
+    }
+
+    Ok(animation)
+}
+
+fn add_frame(animation: &mut Animation, frame: &Frame, duration: Option<Scalar>) {
+    match duration {
+        Some(duration) => animation.add_frame_with_duration(frame.clone(), duration),
+        None => animation.add_frame(frame.clone()),
+    }
+}
+
+fn parse_frame(image: &ImageRef, image_h: i32, entry: &Value) -> Result<(Frame, Option<Scalar>), Error> {
+    if entry.find("rotated").and_then(|rotated| rotated.as_bool()).unwrap_or(false) {
+        return Err(Error::Decode("Rotated frames are not supported".to_string()));
+    }
+
+    let rect = try!(field(entry, "frame"));
+    let (x, y, w, h) = (try!(int(rect, "x")), try!(int(rect, "y")), try!(int(rect, "w")), try!(int(rect, "h")));
+
+    // Sheets count rows from the top, images from the bottom
+    let region = Rect::with_min_size(vector(x as Scalar, (image_h - y - h) as Scalar),
+                                     vector(w as Scalar, h as Scalar));
+    let mut frame = Frame::new(image.clone(), region);
+
+    // Trimmed frames are offset inside the untrimmed source, which is where the pivot is defined.
+    let (trim_x, trim_y) = match entry.find("spriteSourceSize") {
+        Some(trim) => (try!(int(trim, "x")), try!(int(trim, "y"))),
+        None => (0, 0),
+    };
+    let (source_w, source_h) = match entry.find("sourceSize") {
+        Some(source) => (try!(int(source, "w")), try!(int(source, "h"))),
+        None => (w, h),
+    };
+    let (pivot_x, pivot_y) = match entry.find("pivot") {
+        Some(pivot) => (try!(float(pivot, "x")), try!(float(pivot, "y"))),
+        None => (0.0, 1.0),
+    };
+
+    if w > 0 && h > 0 {
+        let pivot = vector(pivot_x * source_w as Scalar - trim_x as Scalar,
+                           pivot_y * source_h as Scalar - trim_y as Scalar);
+        frame.set_anchor(Vector {
+            x: pivot.x / w as Scalar,
+            y: (h as Scalar - pivot.y) / h as Scalar,
+        });
+    }
+
+    let duration = match entry.find("duration") {
+        Some(_) => Some(try!(float(entry, "duration")) / 1000.0),
+        None => None,
+    };
+
+    Ok((frame, duration))
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Result<&'a Value, Error> {
+    value.find(key).ok_or_else(|| Error::Decode(format!("Missing field `{}`", key)))
+}
+
+fn int(value: &Value, key: &str) -> Result<i32, Error> {
+    try!(field(value, key)).as_i64().map(|v| v as i32).ok_or_else(|| Error::Decode(format!("Field `{}` is not an integer", key)))
+}
+
+fn float(value: &Value, key: &str) -> Result<Scalar, Error> {
+    try!(field(value, key)).as_f64().map(|v| v as Scalar).ok_or_else(|| Error::Decode(format!("Field `{}` is not a number", key)))
+}
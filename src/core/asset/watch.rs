@@ -0,0 +1,249 @@
+//! Hot reloading. Once enabled, every asset loaded from a file is loaded again
+//! when the file changes and the result is written into the existing
+//! `AssetRef`, so all holders see the new version.
+
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::{Asset, Loadable, Slot};
+
+use Error;
+
+use vfs;
+
+// Returns false once the asset was unloaded, so it isn't watched anymore
+type Reloader = Arc<Fn() -> bool + Send + Sync>;
+
+struct Watcher {
+    inotify: sys::Inotify,
+    dirs: HashSet<PathBuf>,
+    reloaders: HashMap<PathBuf, HashMap<TypeId, Reloader>>,
+}
+
+lazy_static! {
+    static ref WATCHER: Mutex<Option<Watcher>> = Mutex::new(None);
+}
+
+/// Starts watching the files of the assets loaded from now on, call this
+/// before loading anything.
+pub fn enable_hot_reload() -> Result<(), Error> {
+    let mut watcher = WATCHER.lock().unwrap();
+    if watcher.is_none() {
+        *watcher = Some(Watcher {
+            inotify: try!(sys::Inotify::new()),
+            dirs: HashSet::new(),
+            reloaders: HashMap::new(),
+        });
+        info!("Hot reloading enabled");
+    }
+    Ok(())
+}
+
+/// Reloads the assets whose files changed since the last call. `hammer::run`
+/// calls this once per frame when hot reloading is enabled.
+pub fn reload_changed() {
+    let reloaders = {
+        let mut watcher = WATCHER.lock().unwrap();
+        let watcher = match *watcher {
+            Some(ref mut watcher) => watcher,
+            None => return,
+        };
+
+        // Editors usually write a file in several steps, reload it only once
+        let changed = watcher.inotify.changed_files().into_iter().collect::<HashSet<_>>();
+
+        let mut reloaders = Vec::new();
+        for path in changed {
+            if let Some(path_reloaders) = watcher.reloaders.get(&path) {
+                reloaders.extend(path_reloaders.iter().map(|(&type_id, reloader)| (path.clone(), type_id, reloader.clone())));
+            }
+        }
+        reloaders
+    };
+
+    // Loading may load other assets, which registers them with the watcher
+    let mut unloaded = Vec::new();
+    for (path, type_id, reloader) in reloaders {
+        if !reloader() {
+            unloaded.push((path, type_id, reloader));
+        }
+    }
+
+    if let Some(ref mut watcher) = *WATCHER.lock().unwrap() {
+        for (path, type_id, reloader) in unloaded {
+            let is_empty = match watcher.reloaders.get_mut(&path) {
+                Some(path_reloaders) => {
+                    // Unless the asset was loaded again in the meantime
+                    if path_reloaders.get(&type_id).map_or(false, |current| is_same(current, &reloader)) {
+                        path_reloaders.remove(&type_id);
+                    }
+                    path_reloaders.is_empty()
+                }
+                None => false,
+            };
+            if is_empty {
+                watcher.reloaders.remove(&path);
+            }
+        }
+    }
+}
+
+fn is_same(a: &Reloader, b: &Reloader) -> bool {
+    &**a as *const _ as *const u8 == &**b as *const _ as *const u8
+}
+
+pub fn register<A, F>(path: &Path, slot: &Slot<A>, load: F)
+    where A: Asset + Loadable,
+          F: Fn(&Path) -> Result<A, Error> + Send + Sync + 'static
+{
+    let mut watcher = WATCHER.lock().unwrap();
+    let watcher = match *watcher {
+        Some(ref mut watcher) => watcher,
+        None => return,
+    };
+
+    // Files inside archives can't change
+    let real_path = match vfs::real_path(path).map(fs::canonicalize) {
+        Some(Ok(real_path)) => real_path,
+        Some(Err(e)) => {
+            warn!("Can't watch {}: {}", path.display(), e);
+            return;
+        }
+        None => return,
+    };
+
+    if let Some(dir) = real_path.parent() {
+        if !watcher.dirs.contains(dir) {
+            if let Err(e) = watcher.inotify.add_dir(dir) {
+                warn!("Can't watch {}: {}", dir.display(), e);
+                return;
+            }
+            watcher.dirs.insert(dir.to_path_buf());
+        }
+    }
+
+    // The registry owns the slot, unloading the asset drops it
+    let slot = slot.downgrade();
+    let path = path.to_path_buf();
+    let reloader: Reloader = Arc::new(move || {
+        match slot.upgrade() {
+            Some(slot) => {
+                slot.reload(&path, &load);
+                true
+            }
+            None => false,
+        }
+    });
+    watcher.reloaders.entry(real_path).or_insert_with(|| HashMap::new()).insert(TypeId::of::<A>(), reloader);
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    extern crate libc;
+
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::io;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::slice;
+
+    use Error;
+
+    pub struct Inotify {
+        fd: libc::c_int,
+        dirs: HashMap<libc::c_int, PathBuf>,
+    }
+
+    impl Inotify {
+        pub fn new() -> Result<Inotify, Error> {
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            Ok(Inotify {
+                fd: fd,
+                dirs: HashMap::new(),
+            })
+        }
+
+        pub fn add_dir(&mut self, dir: &Path) -> Result<(), Error> {
+            let c_dir = try!(CString::new(dir.as_os_str().as_bytes()));
+            // Saving through a temporary file ends with a rename, hence MOVED_TO
+            let wd = unsafe { libc::inotify_add_watch(self.fd, c_dir.as_ptr(), libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            self.dirs.insert(wd, dir.to_path_buf());
+            Ok(())
+        }
+
+        pub fn changed_files(&mut self) -> Vec<PathBuf> {
+            let mut files = Vec::new();
+
+            // u32 so the events are properly aligned, the kernel pads the names to keep them aligned
+            let mut buffer = [0u32; 1024];
+            loop {
+                let len = unsafe {
+                    libc::read(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, mem::size_of_val(&buffer))
+                };
+                // Fails with EAGAIN when there are no more events
+                if len <= 0 {
+                    break;
+                }
+
+                let bytes = unsafe { slice::from_raw_parts(buffer.as_ptr() as *const u8, len as usize) };
+                let mut offset = 0;
+                while offset < bytes.len() {
+                    let event = unsafe { &*(bytes[offset..].as_ptr() as *const libc::inotify_event) };
+                    let name_start = offset + mem::size_of::<libc::inotify_event>();
+                    let name = &bytes[name_start..name_start + event.len as usize];
+                    let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+
+                    if let Some(dir) = self.dirs.get(&event.wd) {
+                        files.push(dir.join(OsStr::from_bytes(name)));
+                    }
+
+                    offset = name_start + event.len as usize;
+                }
+            }
+
+            files
+        }
+    }
+
+    impl Drop for Inotify {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.fd); }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::path::{Path, PathBuf};
+
+    use Error;
+
+    pub struct Inotify;
+
+    impl Inotify {
+        pub fn new() -> Result<Inotify, Error> {
+            Err(Error::Unsupported("Hot reloading is only supported on Linux".to_string()))
+        }
+
+        pub fn add_dir(&mut self, _dir: &Path) -> Result<(), Error> {
+            Ok(())
+        }
+
+        pub fn changed_files(&mut self) -> Vec<PathBuf> {
+            Vec::new()
+        }
+    }
+}
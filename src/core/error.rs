@@ -0,0 +1,99 @@
+use std::error;
+use std::ffi::NulError;
+use std::fmt;
+use std::io;
+
+use serde_json;
+
+use window;
+
+/// Errors are `Clone` so a failed asset can hand out its error to everyone
+/// who asks for it later, see `asset::<A>::error`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// No file with this path, neither on disk nor in a mounted source.
+    NotFound(String),
+    /// Reading or writing failed for another reason.
+    Io(io::ErrorKind, String),
+    /// The data is malformed, with the reason of the decoder, e.g. stb_image.
+    Decode(String),
+    /// The asset is loaded again while loading it on the same thread, i.e. it refers to itself.
+    AlreadyLoading(String),
+    /// The asset was replaced with `asset::set` while loading it.
+    Interrupted(String),
+    /// Loading panicked, with the panic message.
+    Panicked(String),
+    /// An argument can't be used, e.g. an image too large for an atlas page.
+    InvalidInput(String),
+    /// Not available on this platform.
+    Unsupported(String),
+    /// A shader failed to compile or link, with the info log.
+    GlCompile(String),
+    WindowCreation(String),
+    Other(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NotFound(ref message) => write!(f, "Not found: {}", message),
+            Error::Io(_, ref message) => write!(f, "{}", message),
+            Error::Decode(ref reason) => write!(f, "Failed to decode: {}", reason),
+            Error::AlreadyLoading(ref asset) => write!(f, "{} is already loading on this thread", asset),
+            Error::Interrupted(ref asset) => write!(f, "Loading {} was interrupted", asset),
+            Error::Panicked(ref message) => write!(f, "Loading panicked: {}", message),
+            Error::InvalidInput(ref message) => write!(f, "{}", message),
+            Error::Unsupported(ref message) => write!(f, "Unsupported: {}", message),
+            Error::GlCompile(ref log) => write!(f, "Failed to compile shader: {}", log),
+            Error::WindowCreation(ref reason) => write!(f, "Failed to create window: {}", reason),
+            Error::Other(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::NotFound(_) => "not found",
+            Error::Io(..) => "I/O error",
+            Error::Decode(_) => "failed to decode",
+            Error::AlreadyLoading(_) => "already loading",
+            Error::Interrupted(_) => "loading interrupted",
+            Error::Panicked(_) => "loading panicked",
+            Error::InvalidInput(_) => "invalid input",
+            Error::Unsupported(_) => "unsupported",
+            Error::GlCompile(_) => "failed to compile shader",
+            Error::WindowCreation(_) => "failed to create window",
+            Error::Other(_) => "error",
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        match e.kind() {
+            io::ErrorKind::NotFound => Error::NotFound(e.to_string()),
+            kind => Error::Io(kind, e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Decode(e.to_string())
+    }
+}
+
+impl From<NulError> for Error {
+    fn from(e: NulError) -> Error {
+        Error::Other(e.to_string())
+    }
+}
+
+impl From<window::Error> for Error {
+    fn from(e: window::Error) -> Error {
+        match e {
+            window::Error::WindowCreation(reason) => Error::WindowCreation(reason),
+        }
+    }
+}
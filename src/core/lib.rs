@@ -4,17 +4,24 @@
 #![plugin(serde_macros)]
 
 extern crate typemap;
+extern crate serde_json;
+extern crate flate2;
+extern crate hammer_window as window;
 #[macro_use]
 extern crate log;
 #[macro_use]
 extern crate lazy_static;
 
+pub use error::Error;
+
+mod error;
+
 pub mod prelude;
 
 pub mod asset;
-// pub mod renderer;
+pub mod renderer;
 pub mod math;
 pub mod util;
+pub mod vfs;
 pub mod input;
 
-pub type Error = Box<std::error::Error + Send + Sync>;
@@ -0,0 +1,327 @@
+use math::*;
+
+use super::RenderCamera;
+
+/// Moves a `RenderCamera` the way most games want: it follows a target with
+/// a deadzone, stays inside the level, shakes and zooms.
+///
+/// Call `update` once per step and `apply` to write the result into the
+/// camera, e.g. with `renderer::with_camera_mut`.
+pub struct CameraController {
+    position: Vector,
+    target: Vector,
+    size: Vector,
+    viewport: Rect,
+
+    deadzone: Vector,
+    smoothing: Scalar,
+    bounds: Option<Rect>,
+    zoom: Scalar,
+
+    trauma: Scalar,
+    trauma_decay: Scalar,
+    max_shake_offset: Vector,
+    max_shake_angle: Scalar,
+    time: Scalar,
+}
+
+impl CameraController {
+    /// A camera showing `size` world units around `position` at zoom 1.
+    pub fn new(position: Vector, size: Vector) -> CameraController {
+        CameraController {
+            position: position,
+            target: position,
+            size: size,
+            viewport: Rect::with_min_size(Vector::zero(), vector(1.0, 1.0)),
+
+            deadzone: Vector::zero(),
+            smoothing: 8.0,
+            bounds: None,
+            zoom: 1.0,
+
+            trauma: 0.0,
+            trauma_decay: 1.0,
+            max_shake_offset: size * 0.05,
+            max_shake_angle: 0.05,
+            time: 0.0,
+        }
+    }
+
+    /// Center of the view without shake.
+    pub fn position(&self) -> Vector {
+        self.position
+    }
+
+    /// Moves there immediately, e.g. when the level starts.
+    pub fn set_position(&mut self, position: Vector) {
+        self.position = position;
+        self.target = position;
+        self.clamp_to_bounds();
+    }
+
+    pub fn target(&self) -> Vector {
+        self.target
+    }
+
+    /// Where the camera moves to, usually the player.
+    pub fn follow(&mut self, target: Vector) {
+        self.target = target;
+    }
+
+    pub fn size(&self) -> Vector {
+        self.size
+    }
+
+    pub fn set_size(&mut self, size: Vector) {
+        self.size = size;
+        self.clamp_to_bounds();
+    }
+
+    /// See `RenderCamera::viewport`, used by `apply` and the conversions.
+    pub fn viewport(&self) -> &Rect {
+        &self.viewport
+    }
+
+    pub fn set_viewport(&mut self, viewport: Rect) {
+        self.viewport = viewport;
+    }
+
+    /// The target moves this far from the center in each direction before the
+    /// camera follows it.
+    pub fn deadzone(&self) -> Vector {
+        self.deadzone
+    }
+
+    pub fn set_deadzone(&mut self, deadzone: Vector) {
+        self.deadzone = deadzone;
+    }
+
+    /// How fast the camera catches up per second, 0 snaps to the target.
+    pub fn smoothing(&self) -> Scalar {
+        self.smoothing
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: Scalar) {
+        self.smoothing = smoothing;
+    }
+
+    /// The view never shows anything outside of `bounds`, it is centered if
+    /// the bounds are smaller than the view.
+    pub fn bounds(&self) -> Option<&Rect> {
+        self.bounds.as_ref()
+    }
+
+    pub fn set_bounds(&mut self, bounds: Option<Rect>) {
+        self.bounds = bounds;
+        self.clamp_to_bounds();
+    }
+
+    /// 2 shows half of `size`.
+    pub fn zoom(&self) -> Scalar {
+        self.zoom
+    }
+
+    /// Panics unless `zoom` is positive, the view would be infinite.
+    pub fn set_zoom(&mut self, zoom: Scalar) {
+        assert!(zoom > 0.0, "Camera zoom must be positive, not {}", zoom);
+        self.zoom = zoom;
+        self.clamp_to_bounds();
+    }
+
+    /// The world units currently visible.
+    pub fn view_size(&self) -> Vector {
+        self.size / self.zoom
+    }
+
+    pub fn trauma(&self) -> Scalar {
+        self.trauma
+    }
+
+    /// Shakes the camera, e.g. 0.3 for a hit and 1 for an explosion. The shake
+    /// grows with the square of the trauma, which is at most 1.
+    pub fn add_trauma(&mut self, trauma: Scalar) {
+        self.trauma = (self.trauma + trauma).min(1.0);
+    }
+
+    /// Trauma lost per second.
+    pub fn set_trauma_decay(&mut self, decay: Scalar) {
+        self.trauma_decay = decay;
+    }
+
+    /// The offset and rotation in radians at full trauma.
+    pub fn set_max_shake(&mut self, offset: Vector, angle: Scalar) {
+        self.max_shake_offset = offset;
+        self.max_shake_angle = angle;
+    }
+
+    pub fn update(&mut self, delta: Scalar) {
+        self.time += delta;
+        self.trauma = (self.trauma - self.trauma_decay * delta).max(0.0);
+
+        let mut desired = self.position;
+        let distance = self.target - self.position;
+        if distance.x > self.deadzone.x {
+            desired.x = self.target.x - self.deadzone.x;
+        } else if distance.x < -self.deadzone.x {
+            desired.x = self.target.x + self.deadzone.x;
+        }
+        if distance.y > self.deadzone.y {
+            desired.y = self.target.y - self.deadzone.y;
+        } else if distance.y < -self.deadzone.y {
+            desired.y = self.target.y + self.deadzone.y;
+        }
+
+        // Frame rate independent exponential smoothing
+        if self.smoothing > 0.0 {
+            let t = 1.0 - (-self.smoothing * delta).exp();
+            self.position = self.position + (desired - self.position) * t;
+        } else {
+            self.position = desired;
+        }
+
+        self.clamp_to_bounds();
+    }
+
+    /// Maps the world to the region of the camera, with the shake.
+    pub fn view(&self) -> Transform {
+        let shake = self.trauma * self.trauma;
+        // Smooth noise, a few sines with unrelated frequencies per axis
+        let noise = |seed: Scalar| {
+            let t = self.time * 20.0 + seed;
+            (t.sin() + (t * 2.3).sin() * 0.5 + (t * 4.7).sin() * 0.25) / 1.75
+        };
+        let offset = vector(self.max_shake_offset.x * noise(0.0), self.max_shake_offset.y * noise(17.0)) * shake;
+        let angle = self.max_shake_angle * noise(43.0) * shake;
+
+        Transform::rotate(angle) * Transform::offset(-(self.position + offset))
+    }
+
+    pub fn region(&self) -> Rect {
+        Rect::with_center_size(Vector::zero(), self.view_size())
+    }
+
+    pub fn apply(&self, camera: &mut RenderCamera) {
+        camera.set_region(self.region());
+        camera.set_transform(self.view());
+        camera.set_viewport(self.viewport);
+    }
+
+    /// Converts a position in window pixels, from the bottom left, to the world.
+    pub fn screen_to_world(&self, screen: Vector, window_size: Vector) -> Vector {
+        let min = self.viewport.min() % window_size;
+        let size = self.viewport.size() % window_size;
+        let ndc = vector((screen.x - min.x) / size.x * 2.0 - 1.0, (screen.y - min.y) / size.y * 2.0 - 1.0);
+        self.projection().invert() * ndc
+    }
+
+    /// Converts a world position to window pixels, from the bottom left.
+    pub fn world_to_screen(&self, world: Vector, window_size: Vector) -> Vector {
+        let ndc = self.projection() * world;
+        let min = self.viewport.min() % window_size;
+        let size = self.viewport.size() % window_size;
+        min + (ndc + 1.0) % size * 0.5
+    }
+
+    fn projection(&self) -> Transform {
+        Transform::ortho(self.region()) * self.view()
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        let half = self.view_size() * 0.5;
+        let clamp = |value: Scalar, min: Scalar, max: Scalar| {
+            if min > max {
+                (min + max) * 0.5
+            } else if value < min {
+                min
+            } else if value > max {
+                max
+            } else {
+                value
+            }
+        };
+        self.position.x = clamp(self.position.x, bounds.left() + half.x, bounds.right() - half.x);
+        self.position.y = clamp(self.position.y, bounds.bottom() + half.y, bounds.top() - half.y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use math::{Rect, Scalar, Vector, vector};
+
+    use super::CameraController;
+
+    fn assert_close(a: Vector, b: Vector) {
+        assert!((a.x - b.x).abs() < 1e-3 && (a.y - b.y).abs() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    fn controller() -> CameraController {
+        let mut controller = CameraController::new(vector(10.0, 20.0), vector(320.0, 180.0));
+        controller.set_viewport(Rect::with_min_size(vector(0.5, 0.0), vector(0.5, 1.0)));
+        controller
+    }
+
+    #[test]
+    fn center_of_viewport() {
+        let controller = controller();
+        let window = vector(800.0, 600.0);
+        assert_close(controller.world_to_screen(vector(10.0, 20.0), window), vector(600.0, 300.0));
+        assert_close(controller.world_to_screen(vector(-150.0, 110.0), window), vector(400.0, 600.0));
+        assert_close(controller.screen_to_world(vector(600.0, 300.0), window), vector(10.0, 20.0));
+    }
+
+    #[test]
+    fn round_trip() {
+        let window = vector(800.0, 600.0);
+        let points = [vector(0.0, 0.0), vector(10.0, 20.0), vector(-123.5, 77.25), vector(400.0, -300.0)];
+
+        let mut controller = controller();
+        controller.set_zoom(2.5);
+        // Shaken, the view is rotated and moved
+        controller.add_trauma(1.0);
+        controller.update(0.1);
+
+        for &world in points.iter() {
+            let screen = controller.world_to_screen(world, window);
+            assert_close(controller.screen_to_world(screen, window), world);
+        }
+        for &screen in points.iter() {
+            let world = controller.screen_to_world(screen, window);
+            assert_close(controller.world_to_screen(world, window), screen);
+        }
+    }
+
+    #[test]
+    fn zoom_scales_around_center() {
+        let window = vector(800.0, 600.0);
+        let mut controller = controller();
+        let before = controller.world_to_screen(vector(20.0, 20.0), window);
+        controller.set_zoom(2.0);
+        let after = controller.world_to_screen(vector(20.0, 20.0), window);
+        let zoom: Scalar = 2.0;
+        assert_close(after - vector(600.0, 300.0), (before - vector(600.0, 300.0)) * zoom);
+    }
+
+    #[test]
+    fn resize_stays_in_bounds() {
+        let mut controller = controller();
+        controller.set_bounds(Some(Rect::with_min_size(vector(0.0, 0.0), vector(400.0, 400.0))));
+        assert_close(controller.position(), vector(160.0, 90.0));
+
+        controller.set_size(vector(360.0, 300.0));
+        assert_close(controller.position(), vector(180.0, 150.0));
+        // Wider than the bounds, centered
+        controller.set_size(vector(500.0, 300.0));
+        assert_close(controller.position(), vector(200.0, 150.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_zoom() {
+        controller().set_zoom(0.0);
+    }
+}
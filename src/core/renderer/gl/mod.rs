@@ -6,129 +6,239 @@ use window::Window;
 
 use self::wrapper::*;
 
-use asset::*;
+use asset::{Image, ImageRef, WeakImageRef, TextureOptions};
+
 use math::*;
 
+use super::{RenderBackend, ImageRegion, RenderTarget, Material, Uniform};
+
 pub mod wrapper;
 
-pub type TextureCache = HashMap<usize, Texture>;
+/// Textures of the drawn images by `Image::id` and options, an image sampled
+/// in two ways has two. An entry is dropped once its image is gone or was
+/// reloaded, which gives the image a new id.
+pub struct TextureCache {
+    textures: HashMap<(usize, TextureOptions), (WeakImageRef, Texture)>,
+}
+
+impl TextureCache {
+    pub fn new() -> TextureCache {
+        TextureCache {
+            textures: HashMap::new(),
+        }
+    }
+
+    pub fn get_or_create(&mut self, context: &Context, image_ref: &ImageRef, options: &TextureOptions) -> Result<&Texture, Error> {
+        let image = image_ref.read();
+        let key = (image.id(), *options);
+        if !self.textures.contains_key(&key) {
+            let texture = try!(Texture::new(context, &*image, options));
+            self.textures.insert(key, (image_ref.downgrade(), texture));
+        }
+        Ok(&self.textures[&key].1)
+    }
+
+    /// Drops the textures whose images are unloaded or changed, returns how many.
+    pub fn evict(&mut self) -> usize {
+        let stale = self.textures.iter().filter(|&(&(id, _), &(ref image, _))| {
+            match image.upgrade() {
+                Some(image) => image.read().id() != id,
+                None => true,
+            }
+        }).map(|(&key, _)| key).collect::<Vec<_>>();
+
+        for key in stale.iter() {
+            self.textures.remove(key);
+        }
+
+        stale.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+}
 
 pub struct Renderer {
     context: Context,
-    quad: QuadProgram,
+    batch: SpriteBatch,
+    size: (i32, i32),
 
     textures: TextureCache,
+    // Framebuffers of the render targets by `Image::id` of their image
+    framebuffers: HashMap<usize, (WeakImageRef, Framebuffer)>,
+    target: Option<usize>,
 }
 
 impl Renderer {
     pub fn new(window: &Window) -> Result<Renderer, Error> {
-        let context = Context::new(window);
+        let context = try!(Context::new(window));
 
-        let quad = try!(QuadProgram::new(&context));
+        let batch = try!(SpriteBatch::new(&context));
 
         Ok(Renderer {
             context: context,
-            quad: quad,
+            batch: batch,
+            size: window.size(),
 
             textures: TextureCache::new(),
+            framebuffers: HashMap::new(),
+            target: None,
         })
     }
 
-    pub fn clear(&mut self, r: f32, g: f32, b: f32, a: f32) {
-        self.context.clear_color(r, g, b, a);
-        self.context.clear();
-    }
-
-    // pub fn resize(&mut self, w: i32, h: i32) {
-    //     self.context.viewport(0, 0, w, h);
-    // }
+    /// The texture of `region`, uploaded if it wasn't yet.
+    fn texture_id(&mut self, region: &ImageRegion) -> Option<u32> {
+        let id = region.image().read().id();
+        if let Some(&(_, ref framebuffer)) = self.framebuffers.get(&id) {
+            return Some(framebuffer.texture().id());
+        }
 
-    pub fn present(&mut self) {
-        self.context.swap_buffers();
+        match self.textures.get_or_create(&self.context, region.image(), &region.options()) {
+            Ok(texture) => Some(texture.id()),
+            Err(e) => {
+                error!("Failed to create texture for image {}: {}", id, e);
+                None
+            }
+        }
     }
 
-    pub fn fill_with_texture<T: AsTexture>(&mut self, trans: Transform, dst: Option<&Rect>, texture: &T) {
-        if let Ok(texture) = texture.as_texture(&self.context, &mut self.textures) {
-            self.quad.fill_with_texture(trans, dst.unwrap_or(&texture.option_dst), texture.texture, &texture.src);
+    fn set_material(&mut self, material: Option<&Material>) {
+        let mut textures = Vec::new();
+        if let Some(material) = material {
+            for &(_, ref value) in material.uniforms() {
+                if let Uniform::Texture(ref region) = *value {
+                    let id = self.texture_id(region).unwrap_or(0);
+                    textures.push(id);
+                }
+            }
         }
+        self.batch.set_material(material, textures);
     }
+}
 
-    pub fn fill_with_color(&mut self, trans: Transform, dst: &Rect, r: f32, g: f32, b: f32, a: f32) {
-        self.quad.fill_with_color(trans, dst, r, g, b, a);
+impl RenderBackend for Renderer {
+    fn clear(&mut self, r: f32, g: f32, b: f32, a: f32) {
+        self.batch.flush();
+        self.context.clear_color(r, g, b, a);
+        self.context.clear();
     }
 
-/*
-    fn prepare(&mut self) {
-        unsafe {
-            gl::Enable(gl::FRAMEBUFFER_SRGB);
-            gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
-            gl::BlendEquation(gl::FUNC_ADD);
+    fn resize(&mut self, w: i32, h: i32) {
+        self.size = (w, h);
+        if self.target.is_none() {
+            self.set_viewport(0, 0, w, h);
         }
     }
-*/
-}
 
-pub struct TextureRef<'a> {
-    texture: &'a Texture,
-    src: Rect,
-    option_dst: Rect,
-}
+    fn present(&mut self) {
+        self.batch.flush();
+        self.context.swap_buffers();
 
-pub trait AsTexture: Clone {
-    fn as_texture<'r>(&self, context: &Context, textures: &'r mut TextureCache) -> Result<TextureRef<'r>, Error>;
-}
+        let evicted = self.textures.evict();
+        if evicted > 0 {
+            info!("Evicted {} textures", evicted);
+        }
 
-impl<'a> AsTexture for ImageRef {
-    fn as_texture<'r>(&self, context: &Context, textures: &'r mut TextureCache) -> Result<TextureRef<'r>, Error> {
-        let id = self.read().id();
-        if !textures.contains_key(&id) {
-            match Texture::new(&context, &*self.read()) {
-                Ok(texture) => {
-                    textures.insert(id, texture);
-                }
+        let stale = self.framebuffers.iter().filter(|&(_, &(ref image, _))| {
+            image.upgrade().is_none()
+        }).map(|(&id, _)| id).collect::<Vec<_>>();
+        for id in stale.iter() {
+            self.framebuffers.remove(id);
+        }
+    }
 
-                Err(e) => return Err(e),
+    fn set_target(&mut self, target: Option<&RenderTarget>) {
+        self.batch.flush();
+
+        // Sampling the target reads its mipmaps, which are stale now
+        if let Some(id) = self.target.take() {
+            if let Some(&(ref image, ref framebuffer)) = self.framebuffers.get(&id) {
+                if image.upgrade().map_or(false, |image| image.read().texture_options().has_mipmaps()) {
+                    framebuffer.texture().generate_mipmaps();
+                }
             }
         }
 
-        let texture = textures.get(&id).unwrap();
-        let size = texture.size();
-        Ok(TextureRef {
-            texture: texture,
-            src: Rect::with_min_size(vector(0.0, 0.0), size),
-            option_dst: Rect::with_min_size(vector(0.0, 0.0), size),
-        })
-    }
-}
+        let target = match target {
+            Some(target) => target,
+            None => {
+                self.context.bind_framebuffer(0);
+                let (w, h) = self.size;
+                self.set_viewport(0, 0, w, h);
+                return;
+            }
+        };
 
-impl<'a> AsTexture for Frame {
-    fn as_texture<'r>(&self, context: &Context, textures: &'r mut TextureCache) -> Result<TextureRef<'r>, Error> {
-        let image = self.image().read();
+        let image = target.image().read();
         let id = image.id();
-        if !textures.contains_key(&id) {
-            match Texture::new(&context, &*image) {
-                Ok(texture) => {
-                    textures.insert(id, texture);
+        if !self.framebuffers.contains_key(&id) {
+            match Framebuffer::new(&self.context, &*image) {
+                Ok(framebuffer) => {
+                    self.framebuffers.insert(id, (target.image().downgrade(), framebuffer));
                 }
+                Err(e) => {
+                    error!("Failed to create render target {}: {}", target.id(), e);
+                    return;
+                }
+            }
+        }
+
+        self.framebuffers[&id].1.bind();
+        let (w, h) = image.size();
+        self.set_viewport(0, 0, w, h);
+        self.target = Some(id);
+    }
+
+    fn set_viewport(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.batch.flush();
+        self.context.viewport(x, y, w, h);
+        // The viewport doesn't limit `clear`
+        self.context.scissor(x, y, w, h);
+    }
+
+    fn fill_with_texture(&mut self, trans: Transform, dst: Option<&Rect>, region: &ImageRegion, material: Option<&Material>) {
+        self.set_material(material);
 
-                Err(e) => return Err(e),
+        let id = region.image().read().id();
+        if let Some(&(_, ref framebuffer)) = self.framebuffers.get(&id) {
+            if self.target == Some(id) {
+                warn!("Render target {} can't be drawn into itself", id);
+                return;
             }
+            self.batch.fill_with_texture(trans, dst.unwrap_or(region.option_dst()), framebuffer.texture(), region.src());
+            return;
         }
 
-        let texture = textures.get(&id).unwrap();
-        let size = self.region().size();
-        let anchor = self.anchor() % size;
-        Ok(TextureRef {
-            texture: texture,
-            src: *self.region(),
-            option_dst: Rect::with_min_size(-anchor, size),
-        })
+        let texture = match self.textures.get_or_create(&self.context, region.image(), &region.options()) {
+            Ok(texture) => texture,
+            Err(e) => {
+                error!("Failed to create texture for image {}: {}", region.image().read().id(), e);
+                return;
+            }
+        };
+
+        self.batch.fill_with_texture(trans, dst.unwrap_or(region.option_dst()), texture, region.src());
+    }
+
+    fn fill_with_color(&mut self, trans: Transform, dst: &Rect, r: f32, g: f32, b: f32, a: f32, material: Option<&Material>) {
+        self.set_material(material);
+        self.batch.fill_with_color(trans, dst, r, g, b, a);
     }
-}
 
-impl<A: Asset + AsTexture> AsTexture for AssetRef<A> {
-    fn as_texture<'r>(&self, context: &Context, textures: &'r mut TextureCache) -> Result<TextureRef<'r>, Error> {
-        self.read().as_texture(context, textures)
+    fn fill_triangles(&mut self, trans: Transform, triangles: &[Vector], r: f32, g: f32, b: f32, a: f32) {
+        self.set_material(None);
+        self.batch.fill_triangles(trans, triangles, r, g, b, a);
+    }
+
+    fn read_pixels(&mut self) -> Image {
+        self.batch.flush();
+        self.context.bind_framebuffer(0);
+        let (w, h) = self.size;
+        let pixels = self.context.read_pixels(w, h);
+        if let Some(id) = self.target {
+            self.framebuffers[&id].1.bind();
+        }
+        Image::from_rgba(w, h, pixels)
     }
 }
@@ -39,3 +39,9 @@ impl ArrayBuffer {
         }
     }
 }
+
+impl Drop for ArrayBuffer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.id); }
+    }
+}
@@ -0,0 +1,61 @@
+use super::gl;
+use super::gl::types::*;
+
+use super::{Context, Texture};
+
+use Error;
+
+use asset::image::Image;
+
+/// A framebuffer object which draws into a texture created from `image`.
+pub struct Framebuffer {
+    context: Context,
+    id: GLuint,
+    texture: Texture,
+}
+
+impl Framebuffer {
+    pub fn new(context: &Context, image: &Image) -> Result<Framebuffer, Error> {
+        let texture = try!(Texture::new(context, image, &image.texture_options()));
+
+        let mut id = 0;
+        context.make_current();
+        unsafe {
+            gl::GenFramebuffers(1, &mut id);
+        }
+
+        let framebuffer = Framebuffer {
+            context: context.clone(),
+            id: id,
+            texture: texture,
+        };
+
+        framebuffer.bind();
+        let status = unsafe {
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, framebuffer.texture.id(), 0);
+            gl::CheckFramebufferStatus(gl::FRAMEBUFFER)
+        };
+        context.bind_framebuffer(0);
+
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(Error::Other(format!("Framebuffer is incomplete: 0x{:x}", status)));
+        }
+
+        Ok(framebuffer)
+    }
+
+    pub fn bind(&self) {
+        self.context.bind_framebuffer(self.id);
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        self.context.make_current();
+        unsafe { gl::DeleteFramebuffers(1, &self.id); }
+    }
+}
@@ -2,28 +2,36 @@ extern crate gl;
 
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::ffi::CStr;
+use std::os::raw::c_char;
 use std::sync::*;
 use std::collections::HashMap;
 
 use self::gl::types::*;
 
-use math::*;
-
-// pub use self::array_buffer::ArrayBuffer;
+pub use self::array_buffer::ArrayBuffer;
+pub use self::framebuffer::Framebuffer;
 pub use self::program::Program;
 pub use self::shader::Shader;
+pub use self::sprite_batch::SpriteBatch;
 pub use self::texture::Texture;
-// pub use self::vertex_array::VertexArray;
+pub use self::vertex_array::VertexArray;
 
-// mod array_buffer;
+mod array_buffer;
+mod framebuffer;
 mod program;
 mod shader;
+mod sprite_batch;
 mod texture;
-// mod vertex_array;
+mod vertex_array;
 
 use window::{Window, GlContext};
+
 use Error;
 
+// From EXT_texture_filter_anisotropic, core since GL 4.6
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
 #[derive(Clone)]
 pub struct Context {
     raw: Rc<RefCell<GlContext>>,
@@ -31,8 +39,8 @@ pub struct Context {
 }
 
 impl Context {
-    pub fn new(window: &Window) -> Context {
-        let mut context = window.create_gl_context();
+    pub fn new(window: &Window) -> Result<Context, Error> {
+        let mut context = try!(window.create_gl_context());
 
         static OPENGL_FUNCTION_INIT: Once = ONCE_INIT;
 
@@ -42,10 +50,10 @@ impl Context {
             gl::load_with(|symbol| { context.load_function(symbol) });
         });
 
-        Context {
+        Ok(Context {
             raw: Rc::new(RefCell::new(context)),
             state: Rc::new(RefCell::new(State::new())),
-        }
+        })
     }
 
     pub fn clear_color(&self, r: f32, g: f32, b: f32, a: f32) {
@@ -63,6 +71,15 @@ impl Context {
         unsafe { gl::Viewport(x, y, w, h); }
     }
 
+    /// Limits `clear` and drawing to this rectangle.
+    pub fn scissor(&self, x: i32, y: i32, w: i32, h: i32) {
+        self.make_current();
+        unsafe {
+            gl::Enable(gl::SCISSOR_TEST);
+            gl::Scissor(x, y, w, h);
+        }
+    }
+
     pub fn active_texture(&self, texture: GLenum) {
         self.make_current();
         let mut state = self.state.borrow_mut();
@@ -101,6 +118,15 @@ impl Context {
         }
     }
 
+    pub fn bind_framebuffer(&self, id: GLuint) {
+        self.make_current();
+        let mut state = self.state.borrow_mut();
+        if state.framebuffer != id {
+            unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, id); }
+            state.framebuffer = id;
+        }
+    }
+
     pub fn use_program(&self, id: GLuint) {
         self.make_current();
         let mut state = self.state.borrow_mut();
@@ -110,6 +136,48 @@ impl Context {
         }
     }
 
+    /// RGBA pixels of the bound framebuffer, rows from bottom to top.
+    pub fn read_pixels(&self, w: i32, h: i32) -> Vec<u8> {
+        self.make_current();
+        let mut pixels = vec![0; (w * h * 4) as usize];
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(0, 0, w, h, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr() as *mut GLvoid);
+        }
+        pixels
+    }
+
+    /// The highest anisotropy textures can use, 1 if the driver has no
+    /// anisotropic filtering.
+    pub fn max_anisotropy(&self) -> f32 {
+        self.make_current();
+        let mut state = self.state.borrow_mut();
+        if let Some(max) = state.max_anisotropy {
+            return max;
+        }
+
+        let max = unsafe {
+            let mut count = 0;
+            gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+            let supported = (0..count as GLuint).any(|i| {
+                let name = gl::GetStringi(gl::EXTENSIONS, i);
+                if name.is_null() {
+                    return false;
+                }
+                let name = CStr::from_ptr(name as *const c_char).to_bytes();
+                name == b"GL_EXT_texture_filter_anisotropic" || name == b"GL_ARB_texture_filter_anisotropic"
+            });
+
+            let mut max = 1.0;
+            if supported {
+                gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+            }
+            max
+        };
+        state.max_anisotropy = Some(max);
+        max
+    }
+
     pub fn swap_buffers(&self) {
         self.make_current();
         self.raw.borrow_mut().swap_buffers();
@@ -126,6 +194,9 @@ struct State {
     texture_2d: HashMap<GLenum, GLuint>,
     array_buffer: GLuint,
     vertex_array: GLuint,
+    framebuffer: GLuint,
+    // Queried on first use
+    max_anisotropy: Option<f32>,
 }
 
 impl State {
@@ -136,184 +207,24 @@ impl State {
             texture_2d: HashMap::new(),
             array_buffer: 0,
             vertex_array: 0,
+            framebuffer: 0,
+            max_anisotropy: None,
         }
     }
 }
 
-/*
 pub enum BufferUsage {
     StaticDraw,
+    DynamicDraw,
+    StreamDraw,
 }
 
 impl BufferUsage {
     pub fn to_gl(&self) -> GLenum {
         match *self {
             BufferUsage::StaticDraw => gl::STATIC_DRAW,
+            BufferUsage::DynamicDraw => gl::DYNAMIC_DRAW,
+            BufferUsage::StreamDraw => gl::STREAM_DRAW,
         }
     }
 }
-*/
-
-pub struct QuadProgram {
-    context: Context,
-    program: Program,
-    vao: GLuint,
-    _vbo: GLuint,
-}
-
-impl QuadProgram {
-    pub fn new(context: &Context) -> Result<QuadProgram, Error> {
-        const VERTEX_SHADER: &'static str = r#"
-        #version 330 core
-
-        uniform mat3 u_trans;
-        uniform mat3 u_tex_trans;
-
-        layout (location = 0)
-        in vec2 pos;
-
-        layout (location = 1)
-        in vec2 texcoord;
-
-        out vec2 v_texcoord;
-
-        void main() {
-            gl_Position = vec4((u_trans * vec3(pos.xy, 1.0)).xy, 0.0, 1.0);
-            v_texcoord = (u_tex_trans * vec3(texcoord, 1.0)).xy;
-        }
-        "#;
-
-        const FRAGMENT_SHADER: &'static str = r#"
-        #version 330 core
-
-        uniform bool u_is_using_texture;
-        uniform sampler2D u_texture0;
-        uniform vec4 u_color;
-
-        in vec2 v_texcoord;
-
-        out vec4 color;
-
-        void main() {
-            if (u_is_using_texture) {
-                color = texture2D(u_texture0, v_texcoord);
-                color = color * u_color;
-            } else {
-                color = u_color;
-            }
-        }
-        "#;
-
-        const VERTICES: [f32; 16] = [
-            // Positions // Texture Coords
-            0.0, 1.0,    0.0, 1.0,
-            1.0, 1.0,    1.0, 1.0,
-            0.0, 0.0,    0.0, 0.0,
-            1.0, 0.0,    1.0, 0.0,
-        ];
-
-        let mut program = try!(Program::compile_and_link(context, VERTEX_SHADER, FRAGMENT_SHADER));
-
-        program.set_uniform_1i("u_texture0", 0);
-
-        /*
-        let mut vbo = ArrayBuffer::new(context);
-        vbo.buffer_data(Some(&VERTICES), BufferUsage::StaticDraw);
-
-        let mut vao = VertexArray::new(context);
-        vao.bind_array_buffer(&vbo);
-        */
-
-        use std::mem;
-        use std::os::raw::c_void;
-
-        let mut vao = 0;
-        let mut vbo = 0;
-
-        unsafe {
-            gl::GenVertexArrays(1, &mut vao);
-            gl::BindVertexArray(vao);
-
-            gl::GenBuffers(1, &mut vbo);
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-            gl::BufferData(gl::ARRAY_BUFFER, (VERTICES.len() * mem::size_of::<f32>()) as GLsizeiptr, VERTICES.as_ptr() as *const c_void, gl::STATIC_DRAW);
-
-            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, (4 * mem::size_of::<GLfloat>()) as i32, 0 as *const c_void);
-            gl::EnableVertexAttribArray(0);
-
-            gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, (4 * mem::size_of::<GLfloat>()) as i32, (2 * mem::size_of::<GLfloat>()) as *const c_void);
-            gl::EnableVertexAttribArray(1);
-
-            gl::BindVertexArray(0);
-        }
-
-        Ok(QuadProgram {
-            context: context.clone(),
-            program: program,
-            vao: vao,
-            _vbo: vbo,
-        })
-    }
-
-    pub fn fill_with_texture(&mut self, trans: Transform, dst: &Rect, texture: &Texture, src: &Rect) {
-        unsafe {
-            gl::Enable(gl::FRAMEBUFFER_SRGB);
-            gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
-            gl::BlendEquation(gl::FUNC_ADD);
-         }
-
-        self.program.active();
-
-        {
-            let min = dst.min();
-            let size = dst.size();
-            let trans = trans * Transform::offset(min) * Transform::scale(size);
-            let mat = trans.to_gl_mat3();
-            self.program.set_uniform_matrix3_fv("u_trans", &mat);
-        }
-
-        {
-            let min = src.min();
-            let size = src.size();
-            let tex_size = texture.size();
-            let trans = Transform::scale(1.0 / tex_size) * Transform::offset(min) * Transform::scale(size);
-            let mat = trans.to_gl_mat3();
-            self.program.set_uniform_matrix3_fv("u_tex_trans", &mat);
-        }
-
-        self.program.set_uniform_1i("u_is_using_texture", 1);
-        self.program.set_uniform_4f("u_color", 1.0, 1.0, 1.0, 1.0);
-        texture.active(0);
-        self.context.bind_vertex_array(self.vao);
-        unsafe { gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4); }
-        self.context.bind_vertex_array(0);
-    }
-
-    pub fn fill_with_color(&mut self, trans: Transform, dst: &Rect, r: f32, g: f32, b: f32, a: f32) {
-        unsafe {
-            gl::Enable(gl::FRAMEBUFFER_SRGB);
-            gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
-            gl::BlendEquation(gl::FUNC_ADD);
-         }
-
-        self.program.active();
-
-        {
-            let min = dst.min();
-            let size = dst.size();
-            let trans = trans * Transform::offset(min) * Transform::scale(size);
-            let mat = trans.to_gl_mat3();
-            self.program.set_uniform_matrix3_fv("u_trans", &mat);
-        }
-
-
-        self.program.set_uniform_1i("u_is_using_texture", 0);
-        self.program.set_uniform_4f("u_color", r, g, b, a);
-
-        self.context.bind_vertex_array(self.vao);
-        unsafe { gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4); }
-        self.context.bind_vertex_array(0);
-    }
-}
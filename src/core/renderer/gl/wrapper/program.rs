@@ -1,4 +1,3 @@
-use std::str;
 use std::ptr;
 use std::ffi::CString;
 
@@ -39,14 +38,14 @@ impl Program {
                         gl::GetProgramInfoLog(program, len, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
 
                         gl::DeleteProgram(program);
-                        Err(From::from(str::from_utf8(&buf).ok().expect("ProgramInfoLog not valid utf8")))
+                        Err(Error::GlCompile(String::from_utf8_lossy(&buf).into_owned()))
                     }
                 }
             } else {
-                Err(From::from("Second parameter is not a FragmentShader".to_string()))
+                Err(Error::GlCompile("Second parameter is not a FragmentShader".to_string()))
             }
         } else {
-            Err(From::from("First parameter is not a VertexShader".to_string()))
+            Err(Error::GlCompile("First parameter is not a VertexShader".to_string()))
         }
     }
 
@@ -71,6 +70,39 @@ impl Program {
         }
     }
 
+    pub fn set_uniform_1f(&mut self, uniform: &str, value: f32) {
+        self.active();
+
+        let loc = self.get_uniform_location(uniform);
+        if loc != -1 {
+            unsafe { gl::Uniform1f(loc, value); }
+        } else {
+            error!("Failed to set value for uniform `{}`", uniform);
+        }
+    }
+
+    pub fn set_uniform_2f(&mut self, uniform: &str, v0: f32, v1: f32) {
+        self.active();
+
+        let loc = self.get_uniform_location(uniform);
+        if loc != -1 {
+            unsafe { gl::Uniform2f(loc, v0, v1); }
+        } else {
+            error!("Failed to set value for uniform `{}`", uniform);
+        }
+    }
+
+    pub fn set_uniform_3f(&mut self, uniform: &str, v0: f32, v1: f32, v2: f32) {
+        self.active();
+
+        let loc = self.get_uniform_location(uniform);
+        if loc != -1 {
+            unsafe { gl::Uniform3f(loc, v0, v1, v2); }
+        } else {
+            error!("Failed to set value for uniform `{}`", uniform);
+        }
+    }
+
     pub fn set_uniform_4f(&mut self, uniform: &str, v0: f32, v1: f32, v2: f32, v3: f32) {
         self.active();
 
@@ -93,6 +125,11 @@ impl Program {
         }
     }
 
+    /// Whether the linked program uses `uniform`, the compiler drops unused ones.
+    pub fn has_uniform(&self, uniform: &str) -> bool {
+        self.get_uniform_location(uniform) != -1
+    }
+
     fn get_uniform_location(&self, uniform: &str) -> i32 {
         let cstr = CString::new(uniform).unwrap();
         unsafe { gl::GetUniformLocation(self.id, cstr.as_ptr()) }
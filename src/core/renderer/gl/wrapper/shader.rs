@@ -1,4 +1,3 @@
-use std::str;
 use std::ptr;
 use std::ffi::CString;
 
@@ -43,7 +42,7 @@ impl Shader {
                 gl::GetShaderInfoLog(shader, len, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
 
                 gl::DeleteShader(shader);
-                Err(From::from(str::from_utf8(&buf).ok().expect("ShaderInfoLog not valid utf8")))
+                Err(Error::GlCompile(String::from_utf8_lossy(&buf).into_owned()))
             }
         }
     }
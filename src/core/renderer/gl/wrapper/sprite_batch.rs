@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::mem;
+
+use super::gl;
+use super::gl::types::*;
+
+use super::{Context, Program, Texture, ArrayBuffer, VertexArray, BufferUsage};
+
+use Error;
+
+use math::*;
+
+use renderer::{Material, Uniform};
+
+// Flush before the vertex buffer grows beyond this many quads.
+const MAX_QUADS: usize = 4096;
+const VERTICES_PER_QUAD: usize = 6;
+
+const VERTEX_SHADER: &'static str = r#"
+#version 330 core
+
+layout (location = 0)
+in vec2 pos;
+
+layout (location = 1)
+in vec2 texcoord;
+
+layout (location = 2)
+in vec4 color;
+
+out vec2 v_texcoord;
+out vec4 v_color;
+
+void main() {
+    gl_Position = vec4(pos, 0.0, 1.0);
+    v_texcoord = texcoord;
+    v_color = color;
+}
+"#;
+
+// Materials replace this one, see `Material`
+const FRAGMENT_SHADER: &'static str = r#"
+#version 330 core
+
+uniform sampler2D u_texture0;
+
+in vec2 v_texcoord;
+in vec4 v_color;
+
+out vec4 color;
+
+void main() {
+    color = texture(u_texture0, v_texcoord) * v_color;
+}
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Vertex {
+    pos: [f32; 2],
+    texcoord: [f32; 2],
+    color: [f32; 4],
+}
+
+/// Collects quads into one vertex buffer and only issues a draw call when
+/// the texture or material changes or `flush` is called.
+///
+/// Vertices are transformed on the CPU, so quads with different transforms
+/// and colors still end up in the same draw call.
+pub struct SpriteBatch {
+    context: Context,
+    program: Program,
+    vao: VertexArray,
+    vbo: ArrayBuffer,
+
+    // 1x1 white texture used for colored quads, so they can share a batch with textured ones.
+    white: Texture,
+
+    vertices: Vec<Vertex>,
+    texture: GLuint,
+
+    // Material of the queued quads with the ids of its textures, in the order
+    // of its texture uniforms
+    material: Option<(Material, Vec<GLuint>)>,
+    // Compiled programs by `Material::id`, `None` if it failed to compile
+    programs: HashMap<usize, Option<Program>>,
+}
+
+impl SpriteBatch {
+    pub fn new(context: &Context) -> Result<SpriteBatch, Error> {
+        let mut program = try!(Program::compile_and_link(context, VERTEX_SHADER, FRAGMENT_SHADER));
+        program.set_uniform_1i("u_texture0", 0);
+
+        let white = try!(Texture::with_data(context, 1, 1, &[255, 255, 255, 255]));
+
+        let vbo = ArrayBuffer::new(context);
+        let mut vao = VertexArray::new(context);
+
+        let stride = mem::size_of::<Vertex>();
+        let float = mem::size_of::<f32>();
+        vao.attrib_pointer(&vbo, 0, 2, stride, 0);
+        vao.attrib_pointer(&vbo, 1, 2, stride, 2 * float);
+        vao.attrib_pointer(&vbo, 2, 4, stride, 4 * float);
+
+        Ok(SpriteBatch {
+            context: context.clone(),
+            program: program,
+            vao: vao,
+            vbo: vbo,
+
+            white: white,
+
+            vertices: Vec::with_capacity(MAX_QUADS * VERTICES_PER_QUAD),
+            texture: 0,
+
+            material: None,
+            programs: HashMap::new(),
+        })
+    }
+
+    pub fn fill_with_texture(&mut self, trans: Transform, dst: &Rect, texture: &Texture, src: &Rect) {
+        let tex_trans = Transform::scale(1.0 / texture.size()) * Transform::offset(src.min()) * Transform::scale(src.size());
+        self.push_quad(trans, dst, texture.id(), tex_trans, [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    pub fn fill_with_color(&mut self, trans: Transform, dst: &Rect, r: f32, g: f32, b: f32, a: f32) {
+        let white = self.white.id();
+        self.push_quad(trans, dst, white, Transform::identity(), [r, g, b, a]);
+    }
+
+    /// Fills every three vertices as a triangle, shapes share a batch with colored quads.
+    pub fn fill_triangles(&mut self, trans: Transform, triangles: &[Vector], r: f32, g: f32, b: f32, a: f32) {
+        let white = self.white.id();
+        for triangle in triangles.chunks(3) {
+            if triangle.len() < 3 {
+                break;
+            }
+
+            self.reserve(white, 3);
+            for vertex in triangle.iter() {
+                let pos = trans * *vertex;
+                self.vertices.push(Vertex {
+                    pos: [pos.x as f32, pos.y as f32],
+                    texcoord: [0.5, 0.5],
+                    color: [r, g, b, a],
+                });
+            }
+        }
+    }
+
+    /// Draws the following quads with `material`, or with the default shader
+    /// if `None`. `textures` are bound to its texture uniforms.
+    pub fn set_material(&mut self, material: Option<&Material>, textures: Vec<GLuint>) {
+        let is_same = match (self.material.as_ref(), material) {
+            (Some(&(ref current, ref current_textures)), Some(material)) => current.key() == material.key() && *current_textures == textures,
+            (None, None) => true,
+            _ => false,
+        };
+
+        if !is_same {
+            self.flush();
+            self.material = material.map(|material| (material.clone(), textures));
+        }
+    }
+
+    /// Draws all the queued quads.
+    pub fn flush(&mut self) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        unsafe {
+            gl::Enable(gl::FRAMEBUFFER_SRGB);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+            gl::BlendEquation(gl::FUNC_ADD);
+        }
+
+        let context = &self.context;
+        let program = match self.material {
+            Some((ref material, ref textures)) => {
+                let program = self.programs.entry(material.id()).or_insert_with(|| compile(context, material));
+                match *program {
+                    Some(ref mut program) => {
+                        set_uniforms(context, program, material, textures);
+                        program
+                    }
+                    None => &mut self.program,
+                }
+            }
+            None => &mut self.program,
+        };
+        program.active();
+
+        self.context.active_texture(gl::TEXTURE0);
+        self.context.bind_texture_2d(self.texture);
+
+        self.vbo.buffer_data(Some(&self.vertices), BufferUsage::StreamDraw);
+
+        self.vao.bind();
+        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, self.vertices.len() as GLsizei); }
+        self.context.bind_vertex_array(0);
+
+        self.vertices.clear();
+    }
+
+    // Flushes unless `count` more vertices with `texture` fit into this batch
+    fn reserve(&mut self, texture: GLuint, count: usize) {
+        if self.texture != texture || self.vertices.len() + count > MAX_QUADS * VERTICES_PER_QUAD {
+            self.flush();
+            self.texture = texture;
+        }
+    }
+
+    fn push_quad(&mut self, trans: Transform, dst: &Rect, texture: GLuint, tex_trans: Transform, color: [f32; 4]) {
+        self.reserve(texture, VERTICES_PER_QUAD);
+
+        let trans = trans * Transform::offset(dst.min()) * Transform::scale(dst.size());
+
+        // Two triangles covering the unit square, in the same order as the old triangle strip
+        let corners = [vector(0.0, 1.0), vector(1.0, 1.0), vector(0.0, 0.0),
+                       vector(0.0, 0.0), vector(1.0, 1.0), vector(1.0, 0.0)];
+
+        for corner in corners.iter() {
+            let pos = trans * *corner;
+            let texcoord = tex_trans * *corner;
+            self.vertices.push(Vertex {
+                pos: [pos.x as f32, pos.y as f32],
+                texcoord: [texcoord.x as f32, texcoord.y as f32],
+                color: color,
+            });
+        }
+    }
+}
+
+fn compile(context: &Context, material: &Material) -> Option<Program> {
+    match Program::compile_and_link(context, VERTEX_SHADER, material.source()) {
+        Ok(mut program) => {
+            if program.has_uniform("u_texture0") {
+                program.set_uniform_1i("u_texture0", 0);
+            }
+            Some(program)
+        }
+        Err(e) => {
+            error!("Failed to compile material {}, using the default shader: {}", material.id(), e);
+            None
+        }
+    }
+}
+
+// Texture uniforms use the units after `u_texture0`
+fn set_uniforms(context: &Context, program: &mut Program, material: &Material, textures: &[GLuint]) {
+    let mut unit = 1;
+    for &(ref name, ref value) in material.uniforms() {
+        if let Uniform::Texture(_) = *value {
+            context.active_texture(gl::TEXTURE0 + unit);
+            context.bind_texture_2d(textures.get(unit as usize - 1).cloned().unwrap_or(0));
+            if program.has_uniform(name) {
+                program.set_uniform_1i(name, unit as i32);
+            }
+            unit += 1;
+            continue;
+        }
+
+        if !program.has_uniform(name) {
+            continue;
+        }
+
+        match *value {
+            Uniform::Float(v) => program.set_uniform_1f(name, v),
+            Uniform::Vec2(v) => program.set_uniform_2f(name, v[0], v[1]),
+            Uniform::Vec3(v) => program.set_uniform_3f(name, v[0], v[1], v[2]),
+            Uniform::Vec4(v) => program.set_uniform_4f(name, v[0], v[1], v[2], v[3]),
+            Uniform::Mat3(ref v) => program.set_uniform_matrix3_fv(name, v),
+            Uniform::Texture(_) => {}
+        }
+    }
+}
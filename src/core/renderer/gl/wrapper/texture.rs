@@ -8,8 +8,12 @@ use super::Context;
 use Error;
 
 use asset::image::Image;
+use asset::{ColorSpace, PixelFormat, TextureOptions, Filter, Wrap};
 use math::*;
 
+// From EXT_texture_filter_anisotropic, core since GL 4.6
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+
 pub struct Texture {
     context: Context,
     id: GLuint,
@@ -17,11 +21,30 @@ pub struct Texture {
 }
 
 impl Texture {
-    pub fn new(context: &Context, image: &Image) -> Result<Texture, Error> {
+    pub fn new(context: &Context, image: &Image, options: &TextureOptions) -> Result<Texture, Error> {
+        let (w, h) = image.size();
+        let srgb = image.color_space() == ColorSpace::Srgb;
+        // (internal format, format, type), only 8 bit colors can be sRGB
+        let (internal_format, format, ty) = match image.format() {
+            PixelFormat::R8 => (gl::R8, gl::RED, gl::UNSIGNED_BYTE),
+            PixelFormat::Rg8 => (gl::RG8, gl::RG, gl::UNSIGNED_BYTE),
+            PixelFormat::Rgb8 => (if srgb { gl::SRGB8 } else { gl::RGB8 }, gl::RGB, gl::UNSIGNED_BYTE),
+            PixelFormat::Rgba8 => (if srgb { gl::SRGB8_ALPHA8 } else { gl::RGBA8 }, gl::RGBA, gl::UNSIGNED_BYTE),
+            PixelFormat::Rgba16F => (gl::RGBA16F, gl::RGBA, gl::HALF_FLOAT),
+            PixelFormat::R32F => (gl::R32F, gl::RED, gl::FLOAT),
+        };
+        Texture::create(context, w, h, image.data(), internal_format, format, ty, options)
+    }
+
+    /// Creates a texture from sRGB encoded RGBA pixels, rows from bottom to top.
+    pub fn with_data(context: &Context, w: i32, h: i32, data: &[u8]) -> Result<Texture, Error> {
+        Texture::create(context, w, h, data, gl::SRGB8_ALPHA8, gl::RGBA, gl::UNSIGNED_BYTE, &TextureOptions::default())
+    }
+
+    fn create(context: &Context, w: i32, h: i32, data: &[u8], internal_format: GLenum, format: GLenum, ty: GLenum,
+              options: &TextureOptions) -> Result<Texture, Error> {
         let mut id = 0;
 
-        let (w, h) = image.size();
-        let data = image.data();
         let size = vector(w as Scalar, h as Scalar);
 
         unsafe {
@@ -29,14 +52,40 @@ impl Texture {
 
             context.bind_texture_2d(id);
 
-            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::SRGB_ALPHA as i32, w as i32, h as i32, 0,
-                           gl::RGBA, gl::UNSIGNED_BYTE, data.as_ptr() as *const c_void);
-
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
-
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            // Rows of the 1, 2 and 3 byte formats are not 4 byte aligned
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, w as i32, h as i32, 0,
+                           format, ty, data.as_ptr() as *const c_void);
+
+            let mipmaps = options.has_mipmaps();
+            if mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
+
+            let (min_filter, mag_filter) = match (options.filter, mipmaps) {
+                (Filter::Nearest, false) => (gl::NEAREST, gl::NEAREST),
+                (Filter::Nearest, true) => (gl::NEAREST_MIPMAP_NEAREST, gl::NEAREST),
+                (Filter::Linear, false) => (gl::LINEAR, gl::LINEAR),
+                (Filter::Linear, true) => (gl::LINEAR_MIPMAP_NEAREST, gl::LINEAR),
+                (Filter::Trilinear, _) => (gl::LINEAR_MIPMAP_LINEAR, gl::LINEAR),
+            };
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter as i32);
+
+            let wrap = match options.wrap {
+                Wrap::Clamp => gl::CLAMP_TO_EDGE,
+                Wrap::Repeat => gl::REPEAT,
+                Wrap::Mirror => gl::MIRRORED_REPEAT,
+            };
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap as i32);
+
+            // Without the extension the parameter is an invalid enum
+            let max_anisotropy = context.max_anisotropy();
+            if options.anisotropy > 1 && max_anisotropy > 1.0 {
+                let anisotropy = (options.anisotropy as f32).min(max_anisotropy);
+                gl::TexParameterf(gl::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY, anisotropy);
+            }
 
             context.bind_texture_2d(0);
         }
@@ -48,11 +97,22 @@ impl Texture {
         })
     }
 
+    /// Regenerates the mipmaps after drawing into the texture.
+    pub fn generate_mipmaps(&self) {
+        self.context.bind_texture_2d(self.id);
+        unsafe { gl::GenerateMipmap(gl::TEXTURE_2D); }
+        self.context.bind_texture_2d(0);
+    }
+
     pub fn active(&self, unit: u32) {
         self.context.active_texture(gl::TEXTURE0 + unit);
         self.context.bind_texture_2d(self.id);
     }
 
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
     pub fn size(&self) -> Vector {
         self.size
     }
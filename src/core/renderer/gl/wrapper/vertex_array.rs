@@ -1,3 +1,5 @@
+use std::os::raw::c_void;
+
 use super::gl;
 use super::gl::types::*;
 
@@ -29,4 +31,21 @@ impl VertexArray {
         array_buffer.bind();
         self.context.bind_vertex_array(0);
     }
+
+    /// Describes `size` floats at `offset` bytes of each `stride` bytes vertex in `array_buffer`.
+    pub fn attrib_pointer(&mut self, array_buffer: &ArrayBuffer, index: GLuint, size: GLint, stride: usize, offset: usize) {
+        self.bind();
+        array_buffer.bind();
+        unsafe {
+            gl::VertexAttribPointer(index, size, gl::FLOAT, gl::FALSE, stride as GLsizei, offset as *const c_void);
+            gl::EnableVertexAttribArray(index);
+        }
+        self.context.bind_vertex_array(0);
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteVertexArrays(1, &self.id); }
+    }
 }
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use util::counter::Counter;
+
+use super::{AsImageRegion, ImageRegion};
+
+lazy_static! {
+    static ref COUNTER: Counter<usize> = Counter::new(0);
+}
+
+/// Value of a uniform declared by the fragment shader of a `Material`.
+#[derive(Clone)]
+pub enum Uniform {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    /// Column major, like GLSL.
+    Mat3([f32; 9]),
+    /// An extra `sampler2D`, bound to the texture units after `u_texture0`.
+    Texture(ImageRegion),
+}
+
+/// A custom fragment shader with its uniform values, attached to a quad with
+/// `TexturedQuad::material` or `ColoredQuad::material`.
+///
+/// The shader replaces the one of the sprite batch and gets the same inputs:
+///
+/// ```glsl
+/// #version 330 core
+///
+/// uniform sampler2D u_texture0;
+///
+/// in vec2 v_texcoord;
+/// in vec4 v_color; // pre-multiplied linear color of the quad
+///
+/// out vec4 color; // pre-multiplied linear color
+/// ```
+///
+/// Clones share the compiled shader, so one material with different uniforms
+/// per sprite only compiles once. Only the GL renderer runs shaders, the soft
+/// renderer draws quads as if they had no material.
+#[derive(Clone)]
+pub struct Material {
+    id: usize,
+    // Changes with every uniform, quads with the same state share a draw call
+    key: usize,
+    source: Arc<String>,
+    uniforms: Vec<(String, Uniform)>,
+}
+
+impl Material {
+    pub fn new(fragment_shader: &str) -> Material {
+        Material {
+            id: COUNTER.next(),
+            key: COUNTER.next(),
+            source: Arc::new(fragment_shader.to_string()),
+            uniforms: Vec::new(),
+        }
+    }
+
+    /// Identifies the shader, the same for all clones.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Identifies the shader and the uniform values.
+    pub fn key(&self) -> usize {
+        self.key
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn uniforms(&self) -> &[(String, Uniform)] {
+        &self.uniforms
+    }
+
+    pub fn set(&mut self, name: &str, value: Uniform) -> &mut Self {
+        match self.uniforms.iter().position(|&(ref uniform, _)| uniform == name) {
+            Some(i) => self.uniforms[i].1 = value,
+            None => self.uniforms.push((name.to_string(), value)),
+        }
+        self.key = COUNTER.next();
+        self
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) -> &mut Self {
+        self.set(name, Uniform::Float(value))
+    }
+
+    pub fn set_vec2(&mut self, name: &str, x: f32, y: f32) -> &mut Self {
+        self.set(name, Uniform::Vec2([x, y]))
+    }
+
+    pub fn set_vec3(&mut self, name: &str, x: f32, y: f32, z: f32) -> &mut Self {
+        self.set(name, Uniform::Vec3([x, y, z]))
+    }
+
+    pub fn set_vec4(&mut self, name: &str, x: f32, y: f32, z: f32, w: f32) -> &mut Self {
+        self.set(name, Uniform::Vec4([x, y, z, w]))
+    }
+
+    pub fn set_mat3(&mut self, name: &str, value: [f32; 9]) -> &mut Self {
+        self.set(name, Uniform::Mat3(value))
+    }
+
+    /// Binds an image, frame or render target, e.g. the palette of a palette swap.
+    pub fn set_texture<T: AsImageRegion>(&mut self, name: &str, texture: &T) -> &mut Self {
+        self.set(name, Uniform::Texture(texture.as_image_region()))
+    }
+}
@@ -1,29 +1,50 @@
 use std::cell::{Cell, RefCell};
-
-mod gl;
-
-use self::gl::Renderer;
-
+use std::collections::HashMap;
+
+pub mod gl;
+pub mod soft;
+mod camera;
+mod material;
+mod post;
+pub mod shape;
+mod target;
+
+pub use self::camera::CameraController;
+pub use self::material::{Material, Uniform};
+pub use self::post::{PostProcess, Effect};
+pub use self::shape::Join;
+pub use self::target::RenderTarget;
+
+use asset::*;
 use math::*;
+use util::color::srgb_to_linear;
 use window::Window;
 
+/// Draws the window's drawables on `layers` with `Transform::ortho(region)`
+/// and the view transform into `viewport`. Without any camera everything is
+/// drawn with the projection set by `set_projection`.
 pub struct RenderCamera {
     region: Rect,
-    // viewport: Rect,
+    viewport: Rect,
     background: (f32, f32, f32, f32),
+    is_clear: bool,
     transform: Transform,
+    layers: (i32, i32),
 }
 
 impl RenderCamera {
     pub fn new(region: Rect) -> RenderCamera {
         RenderCamera {
             region: region,
-            // viewport: Rect::with_min_size(Vector::zero(), vector(1.0, 1.0)),
+            viewport: Rect::with_min_size(Vector::zero(), vector(1.0, 1.0)),
             background: (0.0, 0.0, 0.0, 1.0),
+            is_clear: true,
             transform: Transform::identity(),
+            layers: (i32::min_value(), i32::max_value()),
         }
     }
 
+    /// The view transform, applied to the drawables before the projection.
     pub fn transform(&self) -> Transform {
         self.transform
     }
@@ -32,6 +53,7 @@ impl RenderCamera {
         self.transform = trans;
     }
 
+    /// The part of the world the camera shows.
     pub fn region(&self) -> &Rect {
         &self.region
     }
@@ -40,6 +62,16 @@ impl RenderCamera {
         self.region = region;
     }
 
+    /// Where the camera draws, from (0, 0) at the bottom left to (1, 1) at the
+    /// top right of the window, e.g. one half for split-screen.
+    pub fn viewport(&self) -> &Rect {
+        &self.viewport
+    }
+
+    pub fn set_viewport(&mut self, viewport: Rect) {
+        self.viewport = viewport;
+    }
+
     pub fn background(&self) -> (f32, f32, f32, f32) {
         self.background
     }
@@ -47,6 +79,129 @@ impl RenderCamera {
     pub fn set_background(&mut self, background: (f32, f32, f32, f32)) {
         self.background = background;
     }
+
+    /// Whether the viewport is cleared to `background` first, overlays like a
+    /// HUD shouldn't.
+    pub fn is_clear(&self) -> bool {
+        self.is_clear
+    }
+
+    pub fn set_clear(&mut self, is_clear: bool) {
+        self.is_clear = is_clear;
+    }
+
+    /// The lowest and highest `RenderOrder::layer` drawn by this camera.
+    pub fn layers(&self) -> (i32, i32) {
+        self.layers
+    }
+
+    pub fn set_layers(&mut self, min: i32, max: i32) {
+        self.layers = (min, max);
+    }
+
+    pub fn projection(&self) -> Transform {
+        Transform::ortho(self.region) * self.transform
+    }
+
+    /// The viewport in pixels of a `w` x `h` target.
+    fn viewport_in(&self, w: i32, h: i32) -> (i32, i32, i32, i32) {
+        let x0 = (self.viewport.left() * w as Scalar).round() as i32;
+        let y0 = (self.viewport.bottom() * h as Scalar).round() as i32;
+        let x1 = (self.viewport.right() * w as Scalar).round() as i32;
+        let y1 = (self.viewport.top() * h as Scalar).round() as i32;
+        (x0, y0, x1 - x0, y1 - y0)
+    }
+}
+
+/// The part of an image a textured quad samples from, independent of any backend.
+#[derive(Clone)]
+pub struct ImageRegion {
+    image: ImageRef,
+    src: Rect,
+    option_dst: Rect,
+    options: TextureOptions,
+}
+
+impl ImageRegion {
+    pub fn image(&self) -> &ImageRef {
+        &self.image
+    }
+
+    /// Region of the image in pixels.
+    pub fn src(&self) -> &Rect {
+        &self.src
+    }
+
+    /// Destination used when the quad doesn't specify one.
+    pub fn option_dst(&self) -> &Rect {
+        &self.option_dst
+    }
+
+    /// How the image is sampled, see `Frame::texture_options`.
+    pub fn options(&self) -> TextureOptions {
+        self.options
+    }
+}
+
+pub trait AsImageRegion {
+    fn as_image_region(&self) -> ImageRegion;
+}
+
+impl AsImageRegion for ImageRef {
+    fn as_image_region(&self) -> ImageRegion {
+        let image = self.read();
+        let (w, h) = image.size();
+        let size = vector(w as Scalar, h as Scalar);
+        ImageRegion {
+            image: self.clone(),
+            src: Rect::with_min_size(vector(0.0, 0.0), size),
+            option_dst: Rect::with_min_size(vector(0.0, 0.0), size),
+            options: image.texture_options(),
+        }
+    }
+}
+
+impl AsImageRegion for Frame {
+    fn as_image_region(&self) -> ImageRegion {
+        let size = self.region().size();
+        let anchor = self.anchor() % size;
+        ImageRegion {
+            image: self.image().clone(),
+            src: *self.region(),
+            option_dst: Rect::with_min_size(-anchor, size),
+            options: self.texture_options(),
+        }
+    }
+}
+
+impl AsImageRegion for RenderTarget {
+    fn as_image_region(&self) -> ImageRegion {
+        self.image().as_image_region()
+    }
+}
+
+impl<A: Asset + AsImageRegion> AsImageRegion for AssetRef<A> {
+    fn as_image_region(&self) -> ImageRegion {
+        self.read().as_image_region()
+    }
+}
+
+/// The operations a renderer has to implement to draw the queued drawables.
+pub trait RenderBackend {
+    fn clear(&mut self, r: f32, g: f32, b: f32, a: f32);
+    fn resize(&mut self, w: i32, h: i32);
+    fn present(&mut self);
+    /// Draws into `target` from now on, or into the window if `None`.
+    fn set_target(&mut self, target: Option<&RenderTarget>);
+    /// Draws into this part of the target in pixels only, `set_target` resets
+    /// it to all of the target.
+    fn set_viewport(&mut self, x: i32, y: i32, w: i32, h: i32);
+    fn fill_with_texture(&mut self, trans: Transform, dst: Option<&Rect>, region: &ImageRegion, material: Option<&Material>);
+    fn fill_with_color(&mut self, trans: Transform, dst: &Rect, r: f32, g: f32, b: f32, a: f32, material: Option<&Material>);
+    /// Fills every three vertices as a triangle.
+    fn fill_triangles(&mut self, trans: Transform, triangles: &[Vector], r: f32, g: f32, b: f32, a: f32);
+    /// The pixels drawn into the window so far, sRGB encoded.
+    fn read_pixels(&mut self) -> Image;
 }
 
 #[derive(Ord, PartialOrd, Eq, PartialEq)]
@@ -83,56 +238,94 @@ impl Trans {
         }
     }
 
-    pub fn texture<T: gl::AsTexture>(self, texture: &T) -> TexturedQuad<T> {
+    pub fn texture<T: AsImageRegion>(self, texture: &T) -> TexturedQuad {
         TexturedQuad {
-            texture: texture.clone(),
+            region: texture.as_image_region(),
             dst: None,
             trans: self.trans,
+            material: None,
+        }
+    }
+
+    pub fn line(self, a: Vector, b: Vector, width: Scalar) -> Shape {
+        self.shape(shape::line(a, b, width))
+    }
+
+    pub fn circle(self, center: Vector, radius: Scalar) -> Shape {
+        self.shape(shape::circle(center, radius, shape::CIRCLE_SEGMENTS))
+    }
+
+    pub fn circle_outline(self, center: Vector, radius: Scalar, width: Scalar) -> Shape {
+        self.shape(shape::circle_outline(center, radius, width, shape::CIRCLE_SEGMENTS))
+    }
+
+    pub fn polygon(self, points: &[Vector]) -> Shape {
+        self.shape(shape::polygon(points))
+    }
+
+    pub fn polyline(self, points: &[Vector], width: Scalar, join: Join) -> Shape {
+        self.shape(shape::polyline(points, width, join, false))
+    }
+
+    pub fn rect_outline(self, rect: Rect, width: Scalar) -> Shape {
+        self.shape(shape::rect_outline(&rect, width))
+    }
+
+    /// Any triangles, e.g. from the functions in `shape`.
+    pub fn shape(self, triangles: Vec<Vector>) -> Shape {
+        Shape {
+            triangles: triangles,
+            trans: self.trans,
         }
     }
 }
 
+// sRGB decoding like the texture sampler's, and pre-multiply alpha
+fn to_linear_premultiplied(r: f32, g: f32, b: f32, a: f32) -> (f32, f32, f32, f32) {
+    (srgb_to_linear(r) * a, srgb_to_linear(g) * a, srgb_to_linear(b) * a, a)
+}
+
 pub struct Quad {
     rect: Rect,
     trans: Transform,
 }
 
 impl Quad {
-    pub fn texture<T: gl::AsTexture>(self, texture: &T) -> TexturedQuad<T> {
+    pub fn texture<T: AsImageRegion>(self, texture: &T) -> TexturedQuad {
         TexturedQuad {
-            texture: texture.clone(),
+            region: texture.as_image_region(),
             dst: Some(self.rect),
             trans: self.trans,
+            material: None,
         }
     }
 
-    pub fn color(self, mut r: f32, mut g: f32, mut b: f32, a: f32) -> ColoredQuad {
-        // gamma correction and pre-multiply alpha
-        let gamma = 2.1;
-
-        r = r.powf(gamma);
-        g = g.powf(gamma);
-        b = b.powf(gamma);
-
-        r = r * a;
-        g = g * a;
-        b = b * a;
-
+    pub fn color(self, r: f32, g: f32, b: f32, a: f32) -> ColoredQuad {
         ColoredQuad {
-            color: (r, g, b, a),
+            color: to_linear_premultiplied(r, g, b, a),
             dst: self.rect,
             trans: self.trans,
+            material: None,
         }
     }
 }
 
-pub struct TexturedQuad<T> {
-    texture: T,
+pub struct TexturedQuad {
+    region: ImageRegion,
     dst: Option<Rect>,
     trans: Transform,
+    material: Option<Material>,
+}
+
+impl TexturedQuad {
+    /// Draws with the fragment shader of `material` instead of the default one.
+    pub fn material(mut self, material: &Material) -> TexturedQuad {
+        self.material = Some(material.clone());
+        self
+    }
 }
 
-impl<T: gl::AsTexture + 'static> Drawable for TexturedQuad<T> {
+impl Drawable for TexturedQuad {
     fn push(self, order: RenderOrder) {
         CONTEXT.with(|context| {
             context.add_drawable(self, order);
@@ -141,8 +334,9 @@ impl<T: gl::AsTexture + 'static> Drawable for TexturedQuad<T> {
 
     fn draw(&self) {
         CONTEXT.with(|context| {
-            if let Some(ref mut renderer) = *context.renderer.borrow_mut() {
-                renderer.fill_with_texture(*context.projection.borrow() * self.trans, self.dst.as_ref(), &self.texture);
+            if let Some(ref mut backend) = *context.backend.borrow_mut() {
+                backend.fill_with_texture(*context.projection.borrow() * self.trans, self.dst.as_ref(), &self.region,
+                                          self.material.as_ref());
             }
         });
     }
@@ -151,7 +345,17 @@ impl<T: gl::AsTexture + 'static> Drawable for TexturedQuad<T> {
 pub struct ColoredQuad {
     color: (f32, f32, f32, f32),
     dst: Rect,
-    trans: Transform
+    trans: Transform,
+    material: Option<Material>,
+}
+
+impl ColoredQuad {
+    /// Draws with the fragment shader of `material`, `v_texcoord` goes from
+    /// (0, 0) to (1, 1) across the quad.
+    pub fn material(mut self, material: &Material) -> ColoredQuad {
+        self.material = Some(material.clone());
+        self
+    }
 }
 
 impl Drawable for ColoredQuad {
@@ -163,62 +367,294 @@ impl Drawable for ColoredQuad {
 
     fn draw(&self) {
         CONTEXT.with(|context| {
-            if let Some(ref mut renderer) = *context.renderer.borrow_mut() {
-                renderer.fill_with_color(*context.projection.borrow() * self.trans, &self.dst,
-                                         self.color.0, self.color.1, self.color.2, self.color.3);
+            if let Some(ref mut backend) = *context.backend.borrow_mut() {
+                backend.fill_with_color(*context.projection.borrow() * self.trans, &self.dst,
+                                        self.color.0, self.color.1, self.color.2, self.color.3, self.material.as_ref());
+            }
+        });
+    }
+}
+
+/// Triangles tessellated on the CPU, see `shape`.
+pub struct Shape {
+    triangles: Vec<Vector>,
+    trans: Transform,
+}
+
+impl Shape {
+    pub fn color(self, r: f32, g: f32, b: f32, a: f32) -> ColoredShape {
+        ColoredShape {
+            color: to_linear_premultiplied(r, g, b, a),
+            triangles: self.triangles,
+            trans: self.trans,
+        }
+    }
+}
+
+pub struct ColoredShape {
+    color: (f32, f32, f32, f32),
+    triangles: Vec<Vector>,
+    trans: Transform,
+}
+
+impl Drawable for ColoredShape {
+    fn push(self, order: RenderOrder) {
+        CONTEXT.with(|context| {
+            context.add_drawable(self, order);
+        });
+    }
+
+    fn draw(&self) {
+        CONTEXT.with(|context| {
+            if let Some(ref mut backend) = *context.backend.borrow_mut() {
+                backend.fill_triangles(*context.projection.borrow() * self.trans, &self.triangles,
+                                       self.color.0, self.color.1, self.color.2, self.color.3);
             }
         });
     }
 }
 
+/// The drawables pushed for one target, see `set_render_target`.
+struct Pass {
+    target: Option<RenderTarget>,
+    projection: Transform,
+    drawables: Vec<(RenderOrder, Box<Drawable>)>,
+}
+
+impl Pass {
+    fn new(target: Option<RenderTarget>) -> Pass {
+        Pass {
+            target: target,
+            projection: Transform::identity(),
+            drawables: Vec::new(),
+        }
+    }
+}
+
 struct Context {
-    renderer: RefCell<Option<Renderer>>,
+    backend: RefCell<Option<Box<RenderBackend>>>,
     cameras: RefCell<Vec<RenderCamera>>,
 
     seq: Cell<usize>,
-    drawables: RefCell<Vec<(RenderOrder, Box<Drawable>)>>,
+    // The window's pass is always the first one
+    passes: RefCell<Vec<Pass>>,
+    current: Cell<usize>,
 
     projection: RefCell<Transform>,
+    // Projections of the render targets by their id, the passes only last one frame
+    target_projections: RefCell<HashMap<usize, (WeakImageRef, Transform)>>,
+
+    size: Cell<(i32, i32)>,
+    // Last color passed to `clear`, the window's pass is drawn into a post
+    // target instead of the cleared window while effects are enabled
+    clear_color: Cell<(f32, f32, f32, f32)>,
+    post_process: RefCell<PostProcess>,
+    // The window's pass and every effect but the last draw into these in turn
+    post_targets: RefCell<Vec<RenderTarget>>,
+
+    screenshot_requested: Cell<bool>,
+    screenshot: RefCell<Option<Image>>,
 }
 
 impl Context {
     pub fn new() -> Context {
         Context {
-            renderer: RefCell::new(None),
+            backend: RefCell::new(None),
             cameras: RefCell::new(Vec::new()),
             seq: Cell::new(0),
-            drawables: RefCell::new(Vec::new()),
+            passes: RefCell::new(vec![Pass::new(None)]),
+            current: Cell::new(0),
             projection: RefCell::new(Transform::identity()),
+            target_projections: RefCell::new(HashMap::new()),
+            size: Cell::new((0, 0)),
+            clear_color: Cell::new((0.0, 0.0, 0.0, 0.0)),
+            post_process: RefCell::new(PostProcess::new()),
+            post_targets: RefCell::new(Vec::new()),
+            screenshot_requested: Cell::new(false),
+            screenshot: RefCell::new(None),
         }
     }
 
     pub fn set_target(&self, window: &Window) {
-        *self.renderer.borrow_mut() = Renderer::new(window).ok();
+        self.size.set(window.size());
+        match gl::Renderer::new(window) {
+            Ok(renderer) => self.set_backend(renderer),
+            Err(e) => error!("Failed to create renderer: {}", e),
+        }
+    }
+
+    pub fn set_backend<B: RenderBackend + 'static>(&self, backend: B) {
+        *self.backend.borrow_mut() = Some(Box::new(backend));
+    }
+
+    pub fn release(&self) {
+        *self.backend.borrow_mut() = None;
+    }
+
+    pub fn resize(&self, w: i32, h: i32) {
+        self.size.set((w, h));
+        if let Some(ref mut backend) = *self.backend.borrow_mut() {
+            backend.resize(w, h);
+        }
+    }
+
+    pub fn set_render_target(&self, target: Option<&RenderTarget>) {
+        let mut passes = self.passes.borrow_mut();
+        let id = target.map(|target| target.id());
+        let index = match passes.iter().position(|pass| pass.target.as_ref().map(|target| target.id()) == id) {
+            Some(index) => index,
+            None => {
+                let mut pass = Pass::new(target.cloned());
+                if let Some(id) = id {
+                    if let Some(&(_, projection)) = self.target_projections.borrow().get(&id) {
+                        pass.projection = projection;
+                    }
+                }
+                passes.push(pass);
+                passes.len() - 1
+            }
+        };
+        self.current.set(index);
     }
 
     pub fn set_projection(&self, trans: Transform) {
-        *self.projection.borrow_mut() = trans;
+        let mut passes = self.passes.borrow_mut();
+        let pass = &mut passes[self.current.get()];
+        pass.projection = trans;
+        if let Some(ref target) = pass.target {
+            self.target_projections.borrow_mut().insert(target.id(), (target.image().downgrade(), trans));
+        }
     }
 
     pub fn clear(&self, r: f32, g: f32, b: f32, a: f32) {
-        if let Some(ref mut renderer) = *self.renderer.borrow_mut() {
-            renderer.clear(r, g, b, a);
+        self.clear_color.set((r, g, b, a));
+        if let Some(ref mut backend) = *self.backend.borrow_mut() {
+            backend.clear(r, g, b, a);
         }
     }
 
     pub fn present(&self) {
-        let mut drawables = self.drawables.borrow_mut();
-        drawables.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut passes = self.passes.borrow_mut();
 
-        for (_, drawable) in drawables.drain(..) {
-            drawable.draw();
+        // The targets first in the order they were set, then the window
+        // which may show them
+        let mut window = passes.remove(0);
+        for mut pass in passes.drain(..) {
+            self.draw_pass(&mut pass, false);
         }
+        let effects = self.post_process.borrow().materials();
+        let (w, h) = self.size.get();
+        if effects.is_empty() || w <= 0 || h <= 0 {
+            self.draw_pass(&mut window, true);
+        } else {
+            self.draw_post_processed(&mut window, &effects);
+        }
+        passes.push(window);
 
-        if let Some(ref mut renderer) = *self.renderer.borrow_mut() {
-            renderer.present();
+        if let Some(ref mut backend) = *self.backend.borrow_mut() {
+            if self.screenshot_requested.get() {
+                *self.screenshot.borrow_mut() = Some(backend.read_pixels());
+                self.screenshot_requested.set(false);
+            }
+            backend.present();
         }
 
         self.seq.set(0);
+        self.current.set(0);
+
+        self.target_projections.borrow_mut().retain(|_, &mut (ref image, _)| image.upgrade().is_some());
+    }
+
+    /// Draws the window's pass into a target, then every effect from one
+    /// target into the other and the last one into the window.
+    fn draw_post_processed(&self, window: &mut Pass, effects: &[Material]) {
+        let (w, h) = self.size.get();
+        let mut targets = self.post_targets.borrow_mut();
+        if targets.first().map_or(true, |target| target.size() != (w, h)) {
+            let options = TextureOptions {
+                filter: Filter::Linear,
+                ..TextureOptions::default()
+            };
+            *targets = vec![RenderTarget::with_options(w, h, options), RenderTarget::with_options(w, h, options)];
+        }
+        targets[0].set_clear_color(Some(self.clear_color.get()));
+
+        window.target = Some(targets[0].clone());
+        self.draw_pass(window, true);
+        window.target = None;
+
+        let screen = Rect::with_min_size(vector(-1.0, -1.0), vector(2.0, 2.0));
+        for (i, effect) in effects.iter().enumerate() {
+            let target = if i + 1 < effects.len() { Some(targets[(i + 1) % 2].clone()) } else { None };
+            let mut material = effect.clone();
+            material.set_vec2("u_resolution", w as f32, h as f32)
+                    .set_vec2("u_texel_size", 1.0 / w as f32, 1.0 / h as f32);
+
+            // `clear` already cleared the window, the frame holds the clear
+            // color too and a translucent one would be blended in twice
+            if target.is_none() {
+                if let Some(ref mut backend) = *self.backend.borrow_mut() {
+                    backend.set_target(None);
+                    backend.clear(0.0, 0.0, 0.0, 0.0);
+                }
+            }
+
+            let quad = self.trans(Transform::identity()).rect(screen).texture(&targets[i % 2]).material(&material);
+            let mut pass = Pass::new(target);
+            pass.drawables.push((RenderOrder::new(0, 0), Box::new(quad)));
+            self.draw_pass(&mut pass, false);
+        }
+    }
+
+    /// Draws the drawables of `pass`, once for every camera if `with_cameras`.
+    fn draw_pass(&self, pass: &mut Pass, with_cameras: bool) {
+        let (w, h) = match pass.target {
+            Some(ref target) => target.size(),
+            None => self.size.get(),
+        };
+
+        if let Some(ref mut backend) = *self.backend.borrow_mut() {
+            backend.set_target(pass.target.as_ref());
+            if let Some((r, g, b, a)) = pass.target.as_ref().and_then(|target| target.clear_color()) {
+                backend.clear(r, g, b, a);
+            }
+        }
+
+        pass.drawables.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let cameras = self.cameras.borrow();
+        if !with_cameras || cameras.is_empty() {
+            *self.projection.borrow_mut() = pass.projection;
+            for (_, drawable) in pass.drawables.drain(..) {
+                drawable.draw();
+            }
+            return;
+        }
+
+        for camera in cameras.iter() {
+            let (x, y, viewport_w, viewport_h) = camera.viewport_in(w, h);
+            if let Some(ref mut backend) = *self.backend.borrow_mut() {
+                backend.set_viewport(x, y, viewport_w, viewport_h);
+                if camera.is_clear() {
+                    let (r, g, b, a) = camera.background();
+                    backend.clear(r, g, b, a);
+                }
+            }
+
+            *self.projection.borrow_mut() = camera.projection();
+
+            let (min, max) = camera.layers();
+            for &(ref order, ref drawable) in pass.drawables.iter() {
+                if order.layer >= min && order.layer <= max {
+                    drawable.draw();
+                }
+            }
+        }
+
+        if let Some(ref mut backend) = *self.backend.borrow_mut() {
+            backend.set_viewport(0, 0, w, h);
+        }
+        pass.drawables.clear();
     }
 
     pub fn trans(&self, trans: Transform) -> Trans {
@@ -241,20 +677,53 @@ impl Context {
         }
     }
 
+    pub fn with_camera_mut<F: FnMut(&mut RenderCamera)>(&self, mut f: F) {
+        for camera in self.cameras.borrow_mut().iter_mut() {
+            f(camera);
+        }
+    }
+
     pub fn add_drawable<T: Drawable + 'static>(&self, drawable: T, mut order: RenderOrder) {
         let seq = self.seq.get();
         order.seq = seq;
         self.seq.set(seq + 1);
-        self.drawables.borrow_mut().push((order, Box::new(drawable)));
+        self.passes.borrow_mut()[self.current.get()].drawables.push((order, Box::new(drawable)));
     }
 }
 
 thread_local!(static CONTEXT: Context = Context::new());
 
+/// Renders to `window` with the GL backend, see `set_render_target` to draw
+/// into a `RenderTarget` instead.
 pub fn set_target(window: &Window) {
     CONTEXT.with(|context| context.set_target(window))
 }
 
+/// Renders with a custom backend, e.g. `soft::Renderer`.
+pub fn set_backend<B: RenderBackend + 'static>(backend: B) {
+    CONTEXT.with(|context| context.set_backend(backend))
+}
+
+/// Drops the backend, which must happen before its window is closed.
+pub fn release() {
+    CONTEXT.with(|context| context.release())
+}
+
+pub fn resize(w: i32, h: i32) {
+    CONTEXT.with(|context| context.resize(w, h))
+}
+
+/// Pushes the following drawables into `target`, or into the window if
+/// `None`. `present` switches back to the window.
+///
+/// Every target keeps the projection set with `set_projection` while it was
+/// current, like the window does, until the target is dropped.
+pub fn set_render_target(target: Option<&RenderTarget>) {
+    CONTEXT.with(|context| context.set_render_target(target))
+}
+
+/// Clears the window, or the frame the effects of `with_post_process` start
+/// from while there are any.
 pub fn clear(r: f32, g: f32, b: f32, a: f32) {
     CONTEXT.with(|context| context.clear(r, g, b, a))
 }
@@ -263,6 +732,18 @@ pub fn present() {
     CONTEXT.with(|context| context.present())
 }
 
+/// Reads the window back at the end of the next `present`, see `take_screenshot`.
+pub fn request_screenshot() {
+    CONTEXT.with(|context| context.screenshot_requested.set(true))
+}
+
+/// The frame read back for `request_screenshot`, e.g. to check what a
+/// headless run drew. It outlives `release`.
+pub fn take_screenshot() -> Option<Image> {
+    CONTEXT.with(|context| context.screenshot.borrow_mut().take())
+}
+
+/// Projection of the current target, each target has its own.
 pub fn set_projection(trans: Transform) {
     CONTEXT.with(|context| context.set_projection(trans))
 }
@@ -271,7 +752,7 @@ pub fn rect(rect: Rect) -> Quad {
     CONTEXT.with(|context| context.trans(Transform::identity()).rect(rect))
 }
 
-pub fn texture<T: gl::AsTexture>(texture: &T) -> TexturedQuad<T> {
+pub fn texture<T: AsImageRegion>(texture: &T) -> TexturedQuad {
     CONTEXT.with(|context| context.trans(Transform::identity()).texture(texture))
 }
 
@@ -279,6 +760,40 @@ pub fn trans(trans: Transform) -> Trans {
     CONTEXT.with(|context| context.trans(trans))
 }
 
+pub fn line(a: Vector, b: Vector, width: Scalar) -> Shape {
+    CONTEXT.with(|context| context.trans(Transform::identity()).line(a, b, width))
+}
+
+pub fn circle(center: Vector, radius: Scalar) -> Shape {
+    CONTEXT.with(|context| context.trans(Transform::identity()).circle(center, radius))
+}
+
+pub fn circle_outline(center: Vector, radius: Scalar, width: Scalar) -> Shape {
+    CONTEXT.with(|context| context.trans(Transform::identity()).circle_outline(center, radius, width))
+}
+
+/// Fills a simple polygon, convex or not.
+pub fn polygon(points: &[Vector]) -> Shape {
+    CONTEXT.with(|context| context.trans(Transform::identity()).polygon(points))
+}
+
+/// A thick line through `points`, use `shape::polyline` for closed ones.
+pub fn polyline(points: &[Vector], width: Scalar, join: Join) -> Shape {
+    CONTEXT.with(|context| context.trans(Transform::identity()).polyline(points, width, join))
+}
+
+/// The outline of `rect`, centered on its edges.
+pub fn rect_outline(rect: Rect, width: Scalar) -> Shape {
+    CONTEXT.with(|context| context.trans(Transform::identity()).rect_outline(rect, width))
+}
+
+/// Changes the effects `present` applies to the window, e.g.
+/// `renderer::with_post_process(|post| { post.push(Effect::vignette(0.8, 0.4)); })`.
+pub fn with_post_process<F: FnOnce(&mut PostProcess)>(f: F) {
+    CONTEXT.with(|context| f(&mut *context.post_process.borrow_mut()))
+}
+
+/// Adds a camera drawing the window's drawables, in the order they were added.
 pub fn add_camera(camera: RenderCamera) {
     CONTEXT.with(|context| context.add_camera(camera))
 }
@@ -290,3 +805,8 @@ pub fn clear_camera() {
 pub fn with_camera<F: FnMut(&RenderCamera)>(f: F) {
     CONTEXT.with(|context| context.with_camera(f))
 }
+
+/// Changes the cameras, e.g. to follow the player.
+pub fn with_camera_mut<F: FnMut(&mut RenderCamera)>(f: F) {
+    CONTEXT.with(|context| context.with_camera_mut(f))
+}
@@ -0,0 +1,273 @@
+//! Full-screen effects applied to everything drawn into the window, see
+//! `renderer::with_post_process`.
+//!
+//! Every effect is a `Material` which draws the frame so far into the next
+//! target. Besides its own uniforms each one gets `u_resolution` and
+//! `u_texel_size` of the window.
+
+use super::{AsImageRegion, Material};
+
+const BLOOM: &'static str = r#"
+#version 330 core
+
+uniform sampler2D u_texture0;
+uniform vec2 u_texel_size;
+uniform float u_threshold;
+uniform float u_intensity;
+uniform float u_radius;
+
+in vec2 v_texcoord;
+in vec4 v_color;
+
+out vec4 color;
+
+void main() {
+    vec4 base = texture(u_texture0, v_texcoord);
+
+    // Gaussian blur of the parts brighter than the threshold
+    vec3 glow = vec3(0.0);
+    float total = 0.0;
+    for (int x = -4; x <= 4; x++) {
+        for (int y = -4; y <= 4; y++) {
+            vec2 offset = vec2(x, y) * u_radius / 4.0 * u_texel_size;
+            float weight = exp(-float(x * x + y * y) / 8.0);
+            vec3 c = texture(u_texture0, v_texcoord + offset).rgb;
+            float brightness = max(c.r, max(c.g, c.b));
+            glow += c * max(brightness - u_threshold, 0.0) / max(brightness, 0.0001) * weight;
+            total += weight;
+        }
+    }
+
+    color = base + vec4(glow / total * u_intensity, 0.0);
+}
+"#;
+
+const COLOR_GRADING: &'static str = r#"
+#version 330 core
+
+uniform sampler2D u_texture0;
+uniform sampler2D u_lut;
+uniform float u_lut_size;
+uniform float u_strength;
+
+in vec2 v_texcoord;
+in vec4 v_color;
+
+out vec4 color;
+
+void main() {
+    vec4 base = texture(u_texture0, v_texcoord);
+
+    // LUTs are authored for straight alpha gamma encoded colors
+    vec3 c = base.a > 0.0 ? base.rgb / base.a : vec3(0.0);
+    c = clamp(pow(c, vec3(1.0 / 2.2)), 0.0, 1.0);
+
+    float n = u_lut_size;
+    float slice = c.b * (n - 1.0);
+    float slice0 = floor(slice);
+    float slice1 = min(slice0 + 1.0, n - 1.0);
+    vec2 uv = vec2((c.r * (n - 1.0) + 0.5) / (n * n), (c.g * (n - 1.0) + 0.5) / n);
+
+    // The sampler decodes the sRGB LUT, so the result is linear again
+    vec3 graded = mix(texture(u_lut, uv + vec2(slice0 / n, 0.0)).rgb,
+                      texture(u_lut, uv + vec2(slice1 / n, 0.0)).rgb,
+                      slice - slice0);
+
+    color = vec4(mix(base.rgb, graded * base.a, u_strength), base.a);
+}
+"#;
+
+const CRT: &'static str = r#"
+#version 330 core
+
+uniform sampler2D u_texture0;
+uniform vec2 u_resolution;
+uniform float u_curvature;
+uniform float u_scanlines;
+
+in vec2 v_texcoord;
+in vec4 v_color;
+
+out vec4 color;
+
+void main() {
+    vec2 uv = v_texcoord * 2.0 - 1.0;
+    uv += uv * uv.yx * uv.yx * u_curvature;
+    uv = uv * 0.5 + 0.5;
+
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec4 c = texture(u_texture0, uv);
+    float scanline = sin(uv.y * u_resolution.y * 3.14159265);
+    color = vec4(c.rgb * (1.0 - u_scanlines * scanline * scanline), c.a);
+}
+"#;
+
+const VIGNETTE: &'static str = r#"
+#version 330 core
+
+uniform sampler2D u_texture0;
+uniform float u_radius;
+uniform float u_softness;
+
+in vec2 v_texcoord;
+in vec4 v_color;
+
+out vec4 color;
+
+void main() {
+    vec4 c = texture(u_texture0, v_texcoord);
+    float distance = length(v_texcoord - 0.5) * 1.41421356;
+    color = vec4(c.rgb * (1.0 - smoothstep(u_radius - u_softness, u_radius, distance)), c.a);
+}
+"#;
+
+const PIXELATE: &'static str = r#"
+#version 330 core
+
+uniform sampler2D u_texture0;
+uniform vec2 u_resolution;
+uniform float u_pixel_size;
+
+in vec2 v_texcoord;
+in vec4 v_color;
+
+out vec4 color;
+
+void main() {
+    vec2 cell = u_pixel_size / u_resolution;
+    color = texture(u_texture0, (floor(v_texcoord / cell) + 0.5) * cell);
+}
+"#;
+
+/// One pass of a `PostProcess`.
+pub struct Effect {
+    name: String,
+    material: Material,
+    enabled: bool,
+}
+
+impl Effect {
+    /// Any material, it samples the frame from `u_texture0`.
+    pub fn new(name: &str, material: Material) -> Effect {
+        Effect {
+            name: name.to_string(),
+            material: material,
+            enabled: true,
+        }
+    }
+
+    /// Blurs the colors brighter than `threshold` over the frame. Uniforms
+    /// `u_threshold`, `u_intensity` and `u_radius` in pixels.
+    pub fn bloom(threshold: f32, intensity: f32) -> Effect {
+        let mut material = Material::new(BLOOM);
+        material.set_float("u_threshold", threshold)
+                .set_float("u_intensity", intensity)
+                .set_float("u_radius", 8.0);
+        Effect::new("bloom", material)
+    }
+
+    /// Maps the colors through a lookup table: `size` slices of `size` x `size`
+    /// pixels side by side, blue from left to right, red to the right and green
+    /// up in every slice. Give the LUT image a linear filter in its `.import`
+    /// sidecar. Uniforms `u_lut`, `u_lut_size` and `u_strength`.
+    pub fn color_grading<T: AsImageRegion>(lut: &T, size: u32) -> Effect {
+        let mut material = Material::new(COLOR_GRADING);
+        material.set_texture("u_lut", lut)
+                .set_float("u_lut_size", size as f32)
+                .set_float("u_strength", 1.0);
+        Effect::new("color_grading", material)
+    }
+
+    /// Curved screen with dark lines between the rows of pixels. Uniforms
+    /// `u_curvature` and `u_scanlines`, the darkness of the lines.
+    pub fn crt(curvature: f32, scanlines: f32) -> Effect {
+        let mut material = Material::new(CRT);
+        material.set_float("u_curvature", curvature)
+                .set_float("u_scanlines", scanlines);
+        Effect::new("crt", material)
+    }
+
+    /// Darkens the corners, beyond `radius` where 1 is the distance to a corner.
+    /// Uniforms `u_radius` and `u_softness`.
+    pub fn vignette(radius: f32, softness: f32) -> Effect {
+        let mut material = Material::new(VIGNETTE);
+        material.set_float("u_radius", radius)
+                .set_float("u_softness", softness);
+        Effect::new("vignette", material)
+    }
+
+    /// Draws the frame with square pixels of `pixel_size`. Uniform `u_pixel_size`.
+    pub fn pixelate(pixel_size: f32) -> Effect {
+        let mut material = Material::new(PIXELATE);
+        material.set_float("u_pixel_size", pixel_size);
+        Effect::new("pixelate", material)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    /// Changes the parameters, e.g. `effect.material_mut().set_float("u_intensity", 1.0)`.
+    pub fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+/// The effects applied by `renderer::present`, in order.
+pub struct PostProcess {
+    effects: Vec<Effect>,
+}
+
+impl PostProcess {
+    pub fn new() -> PostProcess {
+        PostProcess {
+            effects: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, effect: Effect) -> &mut Self {
+        self.effects.push(effect);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Effect> {
+        self.effects.iter().find(|effect| effect.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Effect> {
+        self.effects.iter_mut().find(|effect| effect.name == name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Effect> {
+        match self.effects.iter().position(|effect| effect.name == name) {
+            Some(i) => Some(self.effects.remove(i)),
+            None => None,
+        }
+    }
+
+    /// The effects in the order they are applied, to insert or reorder them.
+    pub fn effects(&mut self) -> &mut Vec<Effect> {
+        &mut self.effects
+    }
+
+    /// Materials of the enabled effects.
+    pub fn materials(&self) -> Vec<Material> {
+        self.effects.iter().filter(|effect| effect.enabled).map(|effect| effect.material.clone()).collect()
+    }
+}
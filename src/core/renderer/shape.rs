@@ -0,0 +1,334 @@
+//! Tessellates shapes into triangle lists on the CPU, three vertices per
+//! triangle, for `renderer::line`, `circle`, `polygon` and friends.
+
+use std::f32::consts::PI;
+
+use math::*;
+
+/// Segments of a full circle.
+pub const CIRCLE_SEGMENTS: usize = 32;
+
+// Miter joins longer than this many half widths become bevels
+const MITER_LIMIT: Scalar = 4.0;
+
+/// How thick polylines connect their segments.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Join {
+    Miter,
+    Bevel,
+    Round,
+}
+
+fn cross(a: Vector, b: Vector) -> Scalar {
+    a.x * b.y - a.y * b.x
+}
+
+fn dot(a: Vector, b: Vector) -> Scalar {
+    a.x * b.x + a.y * b.y
+}
+
+fn normal(from: Vector, to: Vector) -> Vector {
+    let dir = (to - from).normalized();
+    vector(-dir.y, dir.x)
+}
+
+/// A line from `a` to `b` with flat ends.
+pub fn line(a: Vector, b: Vector, width: Scalar) -> Vec<Vector> {
+    if a == b {
+        return Vec::new();
+    }
+
+    let n = normal(a, b) * (width * 0.5);
+    vec![a + n, a - n, b + n, b + n, a - n, b - n]
+}
+
+pub fn circle(center: Vector, radius: Scalar, segments: usize) -> Vec<Vector> {
+    let points = circle_points(center, radius, segments);
+    let mut triangles = Vec::with_capacity(segments * 3);
+    for i in 0..points.len() {
+        triangles.push(center);
+        triangles.push(points[i]);
+        triangles.push(points[(i + 1) % points.len()]);
+    }
+    triangles
+}
+
+/// Points on a circle, counter-clockwise.
+pub fn circle_points(center: Vector, radius: Scalar, segments: usize) -> Vec<Vector> {
+    (0..segments).map(|i| {
+        let angle = i as Scalar / segments as Scalar * 2.0 * PI;
+        center + vector(angle.cos(), angle.sin()) * radius
+    }).collect()
+}
+
+/// Fills a simple polygon, convex or not, in any winding order. Self
+/// intersecting polygons are filled partially.
+pub fn polygon(points: &[Vector]) -> Vec<Vector> {
+    let mut triangles = Vec::new();
+    if points.len() < 3 {
+        return triangles;
+    }
+
+    // Ear clipping needs counter-clockwise vertices
+    let mut area = 0.0;
+    for i in 0..points.len() {
+        area += cross(points[i], points[(i + 1) % points.len()]);
+    }
+    let mut indices = (0..points.len()).collect::<Vec<_>>();
+    if area < 0.0 {
+        indices.reverse();
+    }
+
+    let mut i = 0;
+    // Vertices checked since the last ear was clipped, to stop if there are no ears
+    let mut checked = 0;
+    while indices.len() > 3 && checked < indices.len() {
+        let len = indices.len();
+        let a = points[indices[(i + len - 1) % len]];
+        let b = points[indices[i]];
+        let c = points[indices[(i + 1) % len]];
+
+        if is_ear(a, b, c, points, &indices) {
+            triangles.push(a);
+            triangles.push(b);
+            triangles.push(c);
+            indices.remove(i);
+            checked = 0;
+        } else {
+            i += 1;
+            checked += 1;
+        }
+
+        if i >= indices.len() {
+            i = 0;
+        }
+    }
+
+    if indices.len() == 3 {
+        triangles.push(points[indices[0]]);
+        triangles.push(points[indices[1]]);
+        triangles.push(points[indices[2]]);
+    }
+
+    triangles
+}
+
+fn is_ear(a: Vector, b: Vector, c: Vector, points: &[Vector], indices: &[usize]) -> bool {
+    if cross(b - a, c - b) <= 0.0 {
+        return false;
+    }
+
+    !indices.iter().map(|&i| points[i]).any(|p| {
+        p != a && p != b && p != c &&
+        cross(b - a, p - a) > 0.0 && cross(c - b, p - b) > 0.0 && cross(a - c, p - c) > 0.0
+    })
+}
+
+/// A thick line through `points`, back to the first one if `closed`. The
+/// segments don't overlap, so translucent lines are blended evenly.
+pub fn polyline(points: &[Vector], width: Scalar, join: Join, closed: bool) -> Vec<Vector> {
+    let mut triangles = Vec::new();
+
+    let mut points = points.to_vec();
+    points.dedup();
+    if closed && points.len() > 1 && points[0] == points[points.len() - 1] {
+        points.pop();
+    }
+
+    let n = points.len();
+    if n < 2 {
+        return triangles;
+    }
+
+    let half = width * 0.5;
+    let segments = if closed { n } else { n - 1 };
+    let segment_normal = |segment: usize| normal(points[segment], points[(segment + 1) % n]);
+
+    // Left and right corner where each segment starts and ends, left is the
+    // side of its normal
+    let mut starts = vec![(Vector::zero(), Vector::zero()); segments];
+    let mut ends = vec![(Vector::zero(), Vector::zero()); segments];
+
+    for i in 0..n {
+        let p = points[i];
+        let incoming = if i > 0 { Some(i - 1) } else if closed { Some(n - 1) } else { None };
+        let outgoing = if i < segments { Some(i) } else { None };
+
+        let (incoming, outgoing) = match (incoming, outgoing) {
+            (Some(incoming), Some(outgoing)) => (incoming, outgoing),
+            (None, Some(outgoing)) => {
+                let offset = segment_normal(outgoing) * half;
+                starts[outgoing] = (p + offset, p - offset);
+                continue;
+            }
+            (Some(incoming), None) => {
+                let offset = segment_normal(incoming) * half;
+                ends[incoming] = (p + offset, p - offset);
+                continue;
+            }
+            (None, None) => continue,
+        };
+
+        let n0 = segment_normal(incoming);
+        let n1 = segment_normal(outgoing);
+
+        // The outer side of the turn is the one the join is on
+        let side = if cross(vector(n0.y, -n0.x), vector(n1.y, -n1.x)) > 0.0 { -1.0 } else { 1.0 };
+        let sided = |outer: Vector, inner: Vector| if side > 0.0 { (outer, inner) } else { (inner, outer) };
+
+        let sum = n0 + n1;
+        if sum.len_sq() < 1e-6 {
+            // The line turns back on itself
+            let offset = n0 * half;
+            ends[incoming] = (p + offset, p - offset);
+            starts[outgoing] = (p - offset, p + offset);
+            continue;
+        }
+
+        let miter = sum.normalized();
+        let miter_len = half / dot(miter, n0);
+        let inner = p - miter * (miter_len * side);
+
+        if join == Join::Miter && miter_len <= MITER_LIMIT * half {
+            let outer = p + miter * (miter_len * side);
+            ends[incoming] = sided(outer, inner);
+            starts[outgoing] = sided(outer, inner);
+            continue;
+        }
+
+        let outer0 = p + n0 * (half * side);
+        let outer1 = p + n1 * (half * side);
+        ends[incoming] = sided(outer0, inner);
+        starts[outgoing] = sided(outer1, inner);
+
+        if join == Join::Round {
+            let from = (outer0 - p).y.atan2((outer0 - p).x);
+            let mut angle = (outer1 - p).y.atan2((outer1 - p).x) - from;
+            if angle > PI {
+                angle -= 2.0 * PI;
+            } else if angle < -PI {
+                angle += 2.0 * PI;
+            }
+
+            let steps = (angle.abs() / (PI / 8.0)).ceil().max(1.0) as usize;
+            let mut last = outer0;
+            for step in 1..steps + 1 {
+                let a = from + angle * step as Scalar / steps as Scalar;
+                let next = p + vector(a.cos(), a.sin()) * half;
+                triangles.push(inner);
+                triangles.push(last);
+                triangles.push(next);
+                last = next;
+            }
+        } else {
+            triangles.push(inner);
+            triangles.push(outer0);
+            triangles.push(outer1);
+        }
+    }
+
+    for segment in 0..segments {
+        let (l0, r0) = starts[segment];
+        let (l1, r1) = ends[segment];
+        triangles.extend_from_slice(&[l0, r0, l1, l1, r0, r1]);
+    }
+
+    triangles
+}
+
+/// The outline of `rect`, centered on its edges.
+pub fn rect_outline(rect: &Rect, width: Scalar) -> Vec<Vector> {
+    let corners = [rect.min(), vector(rect.right(), rect.bottom()), rect.max(), vector(rect.left(), rect.top())];
+    polyline(&corners, width, Join::Miter, true)
+}
+
+pub fn circle_outline(center: Vector, radius: Scalar, width: Scalar, segments: usize) -> Vec<Vector> {
+    polyline(&circle_points(center, radius, segments), width, Join::Miter, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use math::{Rect, Scalar, Vector, vector};
+
+    use super::{Join, cross, polygon, polyline, rect_outline};
+
+    // Signed, positive if every triangle is counter-clockwise
+    fn area(triangles: &[Vector]) -> Scalar {
+        triangles.chunks(3).map(|t| cross(t[1] - t[0], t[2] - t[0]) * 0.5).sum()
+    }
+
+    fn assert_counter_clockwise(triangles: &[Vector]) {
+        assert_eq!(triangles.len() % 3, 0);
+        for t in triangles.chunks(3) {
+            assert!(cross(t[1] - t[0], t[2] - t[0]) >= 0.0, "{:?}", t);
+        }
+    }
+
+    #[test]
+    fn polygon_convex() {
+        let square = [vector(0.0, 0.0), vector(2.0, 0.0), vector(2.0, 2.0), vector(0.0, 2.0)];
+        let triangles = polygon(&square);
+        assert_eq!(triangles.len(), 6);
+        assert_counter_clockwise(&triangles);
+        assert_eq!(area(&triangles), 4.0);
+    }
+
+    #[test]
+    fn polygon_concave_clockwise() {
+        // An L, clockwise
+        let l = [vector(0.0, 0.0), vector(0.0, 3.0), vector(1.0, 3.0),
+                 vector(1.0, 1.0), vector(2.0, 1.0), vector(2.0, 0.0)];
+        let triangles = polygon(&l);
+        assert_eq!(triangles.len(), (l.len() - 2) * 3);
+        assert_counter_clockwise(&triangles);
+        assert_eq!(area(&triangles), 4.0);
+    }
+
+    #[test]
+    fn polygon_degenerate() {
+        assert!(polygon(&[]).is_empty());
+        assert!(polygon(&[vector(0.0, 0.0), vector(1.0, 0.0)]).is_empty());
+    }
+
+    #[test]
+    fn polyline_straight() {
+        let points = [vector(0.0, 0.0), vector(1.0, 0.0), vector(1.0, 0.0), vector(3.0, 0.0)];
+        for &join in [Join::Miter, Join::Bevel, Join::Round].iter() {
+            let triangles = polyline(&points, 0.5, join, false);
+            assert!((area(&triangles).abs() - 1.5).abs() < 1e-5, "{:?}", join);
+        }
+    }
+
+    #[test]
+    fn polyline_joins() {
+        let points = [vector(0.0, 0.0), vector(2.0, 0.0), vector(2.0, 2.0)];
+        let miter = polyline(&points, 1.0, Join::Miter, false);
+        let bevel = polyline(&points, 1.0, Join::Bevel, false);
+        let round = polyline(&points, 1.0, Join::Round, false);
+
+        assert_eq!(miter.len(), 12);
+        assert_eq!(bevel.len(), 15);
+        assert!(round.len() > bevel.len());
+
+        // 4 long and 1 wide, the miter fills the outer corner square and the
+        // bevel cuts half of it off, the round join is in between
+        assert!((area(&miter).abs() - 4.0).abs() < 1e-5);
+        assert!((area(&bevel).abs() - 3.875).abs() < 1e-5);
+        let round_area = area(&round).abs();
+        assert!(round_area > 3.875 && round_area < 4.0);
+    }
+
+    #[test]
+    fn polyline_degenerate() {
+        assert!(polyline(&[], 1.0, Join::Miter, false).is_empty());
+        assert!(polyline(&[vector(1.0, 1.0), vector(1.0, 1.0)], 1.0, Join::Miter, true).is_empty());
+    }
+
+    #[test]
+    fn rect_outline_area() {
+        let triangles = rect_outline(&Rect::with_min_size(vector(0.0, 0.0), vector(4.0, 2.0)), 1.0);
+        assert_eq!(triangles.len(), 4 * 6);
+        // From 5 x 3 outside to 3 x 1 inside
+        assert!((area(&triangles).abs() - 12.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,431 @@
+//! A pure software renderer which mimics `gl::Renderer`.
+//!
+//! Colors are blended in linear space with pre-multiplied alpha and stored as
+//! sRGB, the same as the GL renderer does with `FRAMEBUFFER_SRGB` enabled, so
+//! the output can be compared with what the GPU draws.
+
+use std::cmp;
+use std::mem;
+
+use asset::{ColorSpace, PixelFormat, Filter, Wrap, Image, ImageRef};
+use math::*;
+
+use util::color::{srgb_to_linear, linear_to_srgb};
+
+use super::{RenderBackend, ImageRegion, RenderTarget, Material};
+
+lazy_static! {
+    static ref SRGB_TO_LINEAR: [f32; 256] = {
+        let mut table = [0.0; 256];
+        for (i, value) in table.iter_mut().enumerate() {
+            *value = srgb_to_linear(i as f32 / 255.0);
+        }
+        table
+    };
+}
+
+fn to_u8(c: f32) -> u8 {
+    (clamp(c, 0.0, 1.0) * 255.0 + 0.5) as u8
+}
+
+fn clamp(v: f32, min: f32, max: f32) -> f32 {
+    if v < min { min } else if v > max { max } else { v }
+}
+
+/// Maps a texel coordinate outside the image back into it, like the GL wrap modes.
+/// An empty image has no texels, it maps everything to 0.
+fn wrap(i: i32, size: i32, wrap: Wrap) -> i32 {
+    if size <= 0 {
+        return 0;
+    }
+
+    match wrap {
+        Wrap::Clamp => if i < 0 { 0 } else if i >= size { size - 1 } else { i },
+        Wrap::Repeat => ((i % size) + size) % size,
+        Wrap::Mirror => {
+            let i = ((i % (size * 2)) + size * 2) % (size * 2);
+            if i < size { i } else { size * 2 - 1 - i }
+        }
+    }
+}
+
+pub struct Renderer {
+    w: i32,
+    h: i32,
+    pixels: Vec<u8>,
+    // x, y, w, h in pixels, see `RenderBackend::set_viewport`
+    viewport: (i32, i32, i32, i32),
+
+    // The window's pixels while drawing into `target`
+    window: Option<(i32, i32, Vec<u8>)>,
+    target: Option<ImageRef>,
+}
+
+impl Renderer {
+    pub fn new(w: i32, h: i32) -> Renderer {
+        Renderer {
+            w: w,
+            h: h,
+            pixels: vec![0; (w * h * 4) as usize],
+            viewport: (0, 0, w, h),
+
+            window: None,
+            target: None,
+        }
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.w, self.h)
+    }
+
+    /// sRGB encoded RGBA pixels, rows from bottom to top like `glReadPixels`.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Stores the pixels drawn into the current target in its image, which
+    /// has the same layout.
+    fn resolve_target(&mut self) {
+        if let Some(image) = self.target.take() {
+            let mut image = image.write();
+            let settings = *image.import_settings();
+            *image = Image::from_rgba_with(self.w, self.h, self.pixels.clone(), settings);
+        }
+    }
+
+    /// Rasterizes `dst` transformed by `trans` into normalized device
+    /// coordinates. `shade` receives the position inside `dst` in [0, 1) and
+    /// returns a pre-multiplied linear color.
+    fn fill<F: Fn(Vector) -> [f32; 4]>(&mut self, trans: Transform, dst: &Rect, shade: F) {
+        let quad = self.viewport_transform() * trans * Transform::offset(dst.min()) * Transform::scale(dst.size());
+
+        let xaxis = quad.xaxis();
+        let yaxis = quad.yaxis();
+        if xaxis.x * yaxis.y - yaxis.x * xaxis.y == 0.0 {
+            return;
+        }
+        let inv = quad.invert();
+
+        let corners = [quad * vector(0.0, 0.0), quad * vector(1.0, 0.0),
+                       quad * vector(0.0, 1.0), quad * vector(1.0, 1.0)];
+        let mut min = corners[0];
+        let mut max = corners[0];
+        for corner in corners.iter() {
+            min.x = min.x.min(corner.x);
+            min.y = min.y.min(corner.y);
+            max.x = max.x.max(corner.x);
+            max.y = max.y.max(corner.y);
+        }
+
+        let (left, bottom, right, top) = self.clip();
+        let x0 = clamp(min.x.floor(), left as Scalar, right as Scalar) as i32;
+        let y0 = clamp(min.y.floor(), bottom as Scalar, top as Scalar) as i32;
+        let x1 = clamp(max.x.ceil(), left as Scalar, right as Scalar) as i32;
+        let y1 = clamp(max.y.ceil(), bottom as Scalar, top as Scalar) as i32;
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                // Sample at pixel center like the GL rasterization rules
+                let uv = inv * vector(x as Scalar + 0.5, y as Scalar + 0.5);
+                if uv.x >= 0.0 && uv.x < 1.0 && uv.y >= 0.0 && uv.y < 1.0 {
+                    let color = shade(uv);
+                    self.blend(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Rasterizes a triangle in pixels with a constant pre-multiplied linear
+    /// color. Pixels on an edge shared by two triangles are only filled once.
+    fn fill_triangle(&mut self, a: Vector, b: Vector, c: Vector, color: [f32; 4]) {
+        let area = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+        if area == 0.0 {
+            return;
+        }
+        // Counter-clockwise, the inside is left of every edge
+        let (b, c) = if area > 0.0 { (b, c) } else { (c, b) };
+
+        let (left, bottom, right, top) = self.clip();
+        let x0 = clamp(a.x.min(b.x).min(c.x).floor(), left as Scalar, right as Scalar) as i32;
+        let y0 = clamp(a.y.min(b.y).min(c.y).floor(), bottom as Scalar, top as Scalar) as i32;
+        let x1 = clamp(a.x.max(b.x).max(c.x).ceil(), left as Scalar, right as Scalar) as i32;
+        let y1 = clamp(a.y.max(b.y).max(c.y).ceil(), bottom as Scalar, top as Scalar) as i32;
+
+        let covers = |from: Vector, to: Vector, p: Vector| {
+            let w = (to.x - from.x) * (p.y - from.y) - (to.y - from.y) * (p.x - from.x);
+            // Of the two triangles sharing an edge only one walks it upwards
+            w > 0.0 || (w == 0.0 && (to.y > from.y || (to.y == from.y && to.x < from.x)))
+        };
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = vector(x as Scalar + 0.5, y as Scalar + 0.5);
+                if covers(a, b, p) && covers(b, c, p) && covers(c, a, p) {
+                    self.blend(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Maps normalized device coordinates to pixels of the viewport.
+    fn viewport_transform(&self) -> Transform {
+        let (x, y, w, h) = self.viewport;
+        let half_size = vector(w as Scalar, h as Scalar) / 2.0;
+        Transform::offset(vector(x as Scalar, y as Scalar)) * Transform::scale(half_size) * Transform::offset(vector(1.0, 1.0))
+    }
+
+    /// The viewport clamped to the pixels, as min and max corners.
+    fn clip(&self) -> (i32, i32, i32, i32) {
+        let (x, y, w, h) = self.viewport;
+        let x0 = cmp::max(x, 0);
+        let y0 = cmp::max(y, 0);
+        (x0, y0, cmp::max(cmp::min(x + w, self.w), x0), cmp::max(cmp::min(y + h, self.h), y0))
+    }
+
+    // BlendFunc(ONE, ONE_MINUS_SRC_ALPHA) in linear space
+    fn blend(&mut self, x: i32, y: i32, src: [f32; 4]) {
+        let i = ((y * self.w + x) * 4) as usize;
+        let dst = &mut self.pixels[i..i + 4];
+        let inv_a = 1.0 - src[3];
+        for c in 0..3 {
+            let d = SRGB_TO_LINEAR[dst[c] as usize];
+            dst[c] = to_u8(linear_to_srgb(src[c] + d * inv_a));
+        }
+        dst[3] = to_u8(src[3] + dst[3] as f32 / 255.0 * inv_a);
+    }
+}
+
+impl RenderBackend for Renderer {
+    fn clear(&mut self, r: f32, g: f32, b: f32, a: f32) {
+        let pixel = [to_u8(linear_to_srgb(r)), to_u8(linear_to_srgb(g)), to_u8(linear_to_srgb(b)), to_u8(a)];
+        let (left, bottom, right, top) = self.clip();
+        for y in bottom..top {
+            let row = (y * self.w) as usize * 4;
+            for dst in self.pixels[row + left as usize * 4..row + right as usize * 4].chunks_mut(4) {
+                dst.copy_from_slice(&pixel);
+            }
+        }
+    }
+
+    fn set_target(&mut self, target: Option<&RenderTarget>) {
+        self.resolve_target();
+
+        match target {
+            Some(target) => {
+                if self.window.is_none() {
+                    let pixels = mem::replace(&mut self.pixels, Vec::new());
+                    self.window = Some((self.w, self.h, pixels));
+                }
+
+                let image = target.image().read();
+                let (w, h) = image.size();
+                self.w = w;
+                self.h = h;
+                self.pixels = image.data().to_vec();
+                self.viewport = (0, 0, w, h);
+                self.target = Some(target.image().clone());
+            }
+
+            None => {
+                if let Some((w, h, pixels)) = self.window.take() {
+                    self.w = w;
+                    self.h = h;
+                    self.pixels = pixels;
+                }
+                self.viewport = (0, 0, self.w, self.h);
+            }
+        }
+    }
+
+    fn set_viewport(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.viewport = (x, y, w, h);
+    }
+
+    fn resize(&mut self, w: i32, h: i32) {
+        if let Some(ref mut window) = self.window {
+            *window = (w, h, vec![0; (w * h * 4) as usize]);
+            return;
+        }
+        self.w = w;
+        self.h = h;
+        self.pixels = vec![0; (w * h * 4) as usize];
+        self.viewport = (0, 0, w, h);
+    }
+
+    fn present(&mut self) {
+        // Nothing to swap, the frame is already in `pixels`.
+    }
+
+    // Materials are GLSL, which only the GL renderer runs
+    fn fill_with_texture(&mut self, trans: Transform, dst: Option<&Rect>, region: &ImageRegion, _: Option<&Material>) {
+        let image = region.image().read();
+        let (w, h) = image.size();
+        if w <= 0 || h <= 0 {
+            return;
+        }
+
+        let data = image.data();
+        let is_srgb = image.color_space() == ColorSpace::Srgb;
+        let is_rgba8 = image.format() == PixelFormat::Rgba8;
+        // Only 8 bit color channels are sRGB encoded, like the GL textures
+        let is_srgb_color = is_srgb && (is_rgba8 || image.format() == PixelFormat::Rgb8);
+        let src = region.src();
+        let src_min = src.min();
+        let src_size = src.size();
+
+        let options = region.options();
+
+        let fetch = |x: i32, y: i32| -> [f32; 4] {
+            let x = wrap(x, w, options.wrap);
+            let y = wrap(y, h, options.wrap);
+            if !is_rgba8 {
+                let mut texel = image.texel(x, y);
+                if is_srgb_color {
+                    for c in 0..3 {
+                        texel[c] = srgb_to_linear(texel[c]);
+                    }
+                }
+                return texel;
+            }
+
+            let i = (y as usize * w as usize + x as usize) * 4;
+            let channel = |c: u8| if is_srgb { SRGB_TO_LINEAR[c as usize] } else { c as f32 / 255.0 };
+            [channel(data[i]), channel(data[i + 1]), channel(data[i + 2]), data[i + 3] as f32 / 255.0]
+        };
+
+        self.fill(trans, dst.unwrap_or(region.option_dst()), |uv| {
+            let texel = src_min + uv % src_size;
+            match options.filter {
+                Filter::Nearest => fetch(texel.x.floor() as i32, texel.y.floor() as i32),
+                // There are no mipmaps, so trilinear is bilinear at any scale
+                Filter::Linear | Filter::Trilinear => {
+                    let x = texel.x - 0.5;
+                    let y = texel.y - 0.5;
+                    let (x0, y0) = (x.floor(), y.floor());
+                    let (fx, fy) = (x - x0, y - y0);
+                    let (x0, y0) = (x0 as i32, y0 as i32);
+
+                    let (a, b) = (fetch(x0, y0), fetch(x0 + 1, y0));
+                    let (c, d) = (fetch(x0, y0 + 1), fetch(x0 + 1, y0 + 1));
+                    let mut color = [0.0; 4];
+                    for i in 0..4 {
+                        let bottom = a[i] + (b[i] - a[i]) * fx;
+                        let top = c[i] + (d[i] - c[i]) * fx;
+                        color[i] = bottom + (top - bottom) * fy;
+                    }
+                    color
+                }
+            }
+        });
+    }
+
+    fn fill_with_color(&mut self, trans: Transform, dst: &Rect, r: f32, g: f32, b: f32, a: f32, _: Option<&Material>) {
+        self.fill(trans, dst, |_| [r, g, b, a]);
+    }
+
+    fn fill_triangles(&mut self, trans: Transform, triangles: &[Vector], r: f32, g: f32, b: f32, a: f32) {
+        let trans = self.viewport_transform() * trans;
+        for triangle in triangles.chunks(3) {
+            if triangle.len() == 3 {
+                self.fill_triangle(trans * triangle[0], trans * triangle[1], trans * triangle[2], [r, g, b, a]);
+            }
+        }
+    }
+
+    fn read_pixels(&mut self) -> Image {
+        match self.window {
+            Some((w, h, ref pixels)) => Image::from_rgba(w, h, pixels.clone()),
+            None => Image::from_rgba(self.w, self.h, self.pixels.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use asset::{Image, ImageRef};
+    use math::{Rect, Transform, vector};
+    use renderer::{self, AsImageRegion, Effect, RenderBackend};
+
+    use super::Renderer;
+
+    fn pixel(renderer: &Renderer, x: i32, y: i32) -> [u8; 4] {
+        let i = ((y * renderer.size().0 + x) * 4) as usize;
+        let pixels = renderer.pixels();
+        [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+    }
+
+    fn screen() -> Rect {
+        Rect::with_min_size(vector(-1.0, -1.0), vector(2.0, 2.0))
+    }
+
+    #[test]
+    fn clear() {
+        let mut renderer = Renderer::new(4, 4);
+        renderer.clear(1.0, 0.5, 0.0, 1.0);
+        for y in 0..4 {
+            for x in 0..4 {
+                // Linear 0.5 is stored as sRGB
+                assert_eq!(pixel(&renderer, x, y), [255, 188, 0, 255]);
+            }
+        }
+
+        renderer.set_viewport(2, 0, 2, 4);
+        renderer.clear(0.0, 0.0, 0.0, 0.0);
+        assert_eq!(pixel(&renderer, 1, 3), [255, 188, 0, 255]);
+        assert_eq!(pixel(&renderer, 2, 3), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn fill_with_color_blends() {
+        let mut renderer = Renderer::new(4, 4);
+        renderer.clear(0.0, 0.0, 0.5, 1.0);
+        // Half transparent red, pre-multiplied, over the left half
+        let left = Rect::with_min_size(vector(-1.0, -1.0), vector(1.0, 2.0));
+        renderer.fill_with_color(Transform::identity(), &left, 0.5, 0.0, 0.0, 0.5, None);
+
+        assert_eq!(pixel(&renderer, 0, 0), [188, 0, 137, 255]);
+        assert_eq!(pixel(&renderer, 1, 3), [188, 0, 137, 255]);
+        assert_eq!(pixel(&renderer, 2, 0), [0, 0, 188, 255]);
+    }
+
+    #[test]
+    fn fill_with_texture_nearest() {
+        // Red and green in the bottom row, blue and white in the top row
+        let image = Image::from_rgba(2, 2, vec![255, 0, 0, 255, 0, 128, 0, 255,
+                                                0, 0, 255, 255, 255, 255, 255, 255]);
+        let region = ImageRef::new(image).as_image_region();
+
+        let mut renderer = Renderer::new(4, 4);
+        renderer.fill_with_texture(Transform::identity(), Some(&screen()), &region, None);
+
+        // Every texel covers 2 x 2 pixels without filtering
+        for &(x, y, expected) in [(0, 0, [255, 0, 0, 255]), (1, 1, [255, 0, 0, 255]),
+                                  (2, 0, [0, 128, 0, 255]), (3, 1, [0, 128, 0, 255]),
+                                  (0, 2, [0, 0, 255, 255]), (1, 3, [0, 0, 255, 255]),
+                                  (2, 2, [255, 255, 255, 255]), (3, 3, [255, 255, 255, 255])].iter() {
+            assert_eq!(pixel(&renderer, x, y), expected, "pixel {}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn translucent_clear_with_effect() {
+        // The renderer's context is per thread
+        thread::spawn(|| {
+            renderer::set_backend(Renderer::new(4, 4));
+            renderer::resize(4, 4);
+            // Materials are ignored here, so the effect copies the frame
+            renderer::with_post_process(|post| { post.push(Effect::vignette(0.8, 0.4)); });
+
+            renderer::clear(0.0, 0.0, 1.0, 0.5);
+            renderer::request_screenshot();
+            renderer::present();
+            renderer::release();
+
+            let screenshot = renderer::take_screenshot().unwrap();
+            assert_eq!(screenshot.pixel(0, 0), [0, 0, 255, 128]);
+            assert_eq!(screenshot.pixel(3, 3), [0, 0, 255, 128]);
+        }).join().unwrap();
+    }
+}
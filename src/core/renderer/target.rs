@@ -0,0 +1,65 @@
+use asset::*;
+
+use util::counter::Counter;
+
+lazy_static! {
+    static ref COUNTER: Counter<usize> = Counter::new(0);
+}
+
+/// An offscreen image the renderer draws into instead of the window.
+///
+/// Everything pushed after `renderer::set_render_target(Some(&target))` is
+/// drawn into it, and it can be drawn like any other texture, e.g.
+/// `renderer::texture(&target)`. Targets are drawn before the window, so the
+/// window shows this frame's content.
+///
+/// The GL renderer keeps the pixels on the GPU, `image()` only holds them
+/// with a backend that draws on the CPU.
+#[derive(Clone)]
+pub struct RenderTarget {
+    id: usize,
+    image: ImageRef,
+    clear_color: Option<(f32, f32, f32, f32)>,
+}
+
+impl RenderTarget {
+    /// A transparent target, sampled like an image with the default import settings.
+    pub fn new(w: i32, h: i32) -> RenderTarget {
+        RenderTarget::with_options(w, h, TextureOptions::default())
+    }
+
+    /// Use `Filter::Nearest` to upscale low resolution pixel art without blurring it.
+    pub fn with_options(w: i32, h: i32, options: TextureOptions) -> RenderTarget {
+        let settings = ImportSettings {
+            texture: options,
+            ..ImportSettings::default()
+        };
+        RenderTarget {
+            id: COUNTER.next(),
+            image: ImageRef::new(Image::from_rgba_with(w, h, vec![0; (w * h * 4) as usize], settings)),
+            clear_color: Some((0.0, 0.0, 0.0, 0.0)),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn image(&self) -> &ImageRef {
+        &self.image
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        self.image.read().size()
+    }
+
+    /// Pre-multiplied linear color the target is cleared with every frame
+    /// before drawing into it, `None` keeps what was drawn before.
+    pub fn clear_color(&self) -> Option<(f32, f32, f32, f32)> {
+        self.clear_color
+    }
+
+    pub fn set_clear_color(&mut self, color: Option<(f32, f32, f32, f32)>) {
+        self.clear_color = color;
+    }
+}
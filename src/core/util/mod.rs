@@ -3,6 +3,8 @@ use std::os::raw::c_char;
 
 pub mod stb_image;
 pub mod counter;
+pub mod color;
+pub mod png;
 
 pub fn cstr_to_string(ptr: *const c_char) -> String {
     unsafe { CStr::from_ptr(ptr).to_string_lossy().into_owned() }
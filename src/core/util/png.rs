@@ -0,0 +1,142 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+lazy_static! {
+    static ref CRC_TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        for (n, entry) in table.iter_mut().enumerate() {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *entry = c;
+        }
+        table
+    };
+}
+
+/// Writes 8-bit RGBA `pixels`, rows from top to bottom, to a PNG file.
+pub fn write_rgba<P: AsRef<Path>>(path: P, w: u32, h: u32, pixels: &[u8]) -> io::Result<()> {
+    let mut file = try!(File::create(path));
+    encode_rgba(&mut file, w, h, pixels)
+}
+
+/// Fails for empty images, which PNG can't hold.
+pub fn encode_rgba<W: Write>(writer: &mut W, w: u32, h: u32, pixels: &[u8]) -> io::Result<()> {
+    if w == 0 || h == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Can't encode an empty {}x{} image as PNG", w, h)));
+    }
+    if pixels.len() != w as usize * h as usize * 4 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("{} bytes are not a {}x{} RGBA image", pixels.len(), w, h)));
+    }
+
+    try!(writer.write_all(&SIGNATURE));
+
+    let mut ihdr = Vec::with_capacity(13);
+    push_u32(&mut ihdr, w);
+    push_u32(&mut ihdr, h);
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+    try!(write_chunk(writer, b"IHDR", &ihdr));
+
+    // Every scanline starts with its filter type, 0 means none
+    let stride = (w * 4) as usize;
+    let mut raw = Vec::with_capacity((stride + 1) * h as usize);
+    for row in pixels.chunks(stride) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::Best);
+    try!(encoder.write_all(&raw));
+    let zlib = try!(encoder.finish());
+    try!(write_chunk(writer, b"IDAT", &zlib));
+
+    write_chunk(writer, b"IEND", &[])
+}
+
+fn write_chunk<W: Write>(writer: &mut W, ty: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut header = Vec::with_capacity(8);
+    push_u32(&mut header, data.len() as u32);
+    header.extend_from_slice(ty);
+    try!(writer.write_all(&header));
+    try!(writer.write_all(data));
+
+    let crc = crc32(crc32(0xffffffff, ty), data) ^ 0xffffffff;
+    let mut footer = Vec::with_capacity(4);
+    push_u32(&mut footer, crc);
+    writer.write_all(&footer)
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+}
+
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc = CRC_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::raw::c_int;
+    use std::slice;
+
+    use util::stb_image::*;
+
+    use super::encode_rgba;
+
+    fn decode(png: &[u8]) -> (i32, i32, Vec<u8>) {
+        unsafe {
+            let mut w = 0;
+            let mut h = 0;
+            let data = stbi_load_from_memory(png.as_ptr(), png.len() as c_int, &mut w, &mut h, ::std::ptr::null_mut(), 4);
+            assert!(!data.is_null(), "stb_image failed to decode the PNG");
+            let pixels = slice::from_raw_parts(data, (w * h * 4) as usize).to_vec();
+            stbi_image_free(data);
+            (w, h, pixels)
+        }
+    }
+
+    #[test]
+    fn decodes_to_same_pixels() {
+        let pixels = (0..3 * 2 * 4).map(|i| (i * 37 % 256) as u8).collect::<Vec<_>>();
+        let mut png = Vec::new();
+        encode_rgba(&mut png, 3, 2, &pixels).unwrap();
+
+        assert_eq!(decode(&png), (3, 2, pixels));
+    }
+
+    #[test]
+    fn compresses() {
+        let pixels = vec![200; 256 * 256 * 4];
+        let mut png = Vec::new();
+        encode_rgba(&mut png, 256, 256, &pixels).unwrap();
+
+        assert!(png.len() < pixels.len() / 100, "{} bytes", png.len());
+        assert_eq!(decode(&png), (256, 256, pixels));
+    }
+
+    #[test]
+    fn rejects_empty_images() {
+        let mut png = Vec::new();
+        assert!(encode_rgba(&mut png, 0, 4, &[]).is_err());
+        assert!(encode_rgba(&mut png, 4, 0, &[]).is_err());
+        assert!(png.is_empty());
+    }
+
+    #[test]
+    fn rejects_wrong_size() {
+        let mut png = Vec::new();
+        assert!(encode_rgba(&mut png, 2, 2, &[0; 12]).is_err());
+    }
+}
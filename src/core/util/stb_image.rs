@@ -1,9 +1,12 @@
-use std::os::raw::{c_char, c_uchar, c_int};
+use std::os::raw::{c_char, c_uchar, c_int, c_float};
 
 #[allow(non_camel_case_types)]
 pub type stbi_uc = c_uchar;
 
 extern {
+    pub fn stbi_load_from_memory(buffer: *const stbi_uc, len: c_int, x: *mut c_int, y: *mut c_int, comp: *mut c_int, req_comp: c_int) -> *mut stbi_uc;
+    pub fn stbi_loadf_from_memory(buffer: *const stbi_uc, len: c_int, x: *mut c_int, y: *mut c_int, comp: *mut c_int, req_comp: c_int) -> *mut c_float;
+    pub fn stbi_is_hdr_from_memory(buffer: *const stbi_uc, len: c_int) -> c_int;
     pub fn stbi_load(filename: *const c_char, x: *mut c_int, y: *mut c_int, comp: *mut c_int, req_comp: c_int) -> *mut stbi_uc;
     pub fn stbi_image_free(retval_from_stbi_load: *mut stbi_uc);
     pub fn stbi_failure_reason() -> *const c_char;
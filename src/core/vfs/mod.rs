@@ -0,0 +1,240 @@
+//! Virtual file system. Directories and archives are mounted at prefixes like
+//! `res://`, and `vfs::open("res://sprites/hero.png")` reads the file from
+//! the mount with the highest priority that has it, so mods and patches can
+//! override single files of the base game.
+//!
+//! Paths without a `scheme://` prefix are looked up in the sources mounted at
+//! the empty prefix first, e.g. a `Pack` of the asset directory, and then read
+//! from the file system as is.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+pub use self::pack::{Pack, PackBuilder, Compression};
+pub use self::zip::Zip;
+
+mod pack;
+mod zip;
+
+/// Something files can be read from, paths are relative to the mount point
+/// and always separated by `/`.
+pub trait Source: Send + Sync {
+    fn exists(&self, path: &str) -> bool;
+
+    fn open(&self, path: &str) -> io::Result<Box<Read + Send>>;
+
+    /// The file on disk, if there is one, used for hot reloading.
+    fn real_path(&self, _path: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+pub struct Directory {
+    root: PathBuf,
+}
+
+impl Directory {
+    pub fn new<P: AsRef<Path>>(root: P) -> Directory {
+        Directory {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl Source for Directory {
+    fn exists(&self, path: &str) -> bool {
+        fs::metadata(self.root.join(path)).map(|metadata| metadata.is_file()).unwrap_or(false)
+    }
+
+    fn open(&self, path: &str) -> io::Result<Box<Read + Send>> {
+        let file = try!(File::open(self.root.join(path)));
+        Ok(Box::new(file))
+    }
+
+    fn real_path(&self, path: &str) -> Option<PathBuf> {
+        Some(self.root.join(path))
+    }
+}
+
+struct Mount {
+    prefix: String,
+    priority: i32,
+    source: Box<Source>,
+}
+
+lazy_static! {
+    // Sorted by priority, highest first. Among the same priority the latest mounted comes first.
+    static ref MOUNTS: RwLock<Vec<Mount>> = RwLock::new(Vec::new());
+}
+
+/// Mounts `source` at `prefix`, e.g. `res://` or `res://music/`.
+pub fn mount<S: Into<String>, T: Source + 'static>(prefix: S, source: T, priority: i32) {
+    let prefix = prefix.into();
+    info!("Mounted {} with priority {}", prefix, priority);
+
+    let mut mounts = MOUNTS.write().unwrap();
+    let index = mounts.iter().position(|mount| mount.priority <= priority).unwrap_or(mounts.len());
+    mounts.insert(index, Mount {
+        prefix: prefix,
+        priority: priority,
+        source: Box::new(source),
+    });
+}
+
+/// Removes every source mounted at `prefix`.
+pub fn unmount(prefix: &str) {
+    MOUNTS.write().unwrap().retain(|mount| mount.prefix != prefix);
+}
+
+pub fn exists<P: AsRef<Path>>(path: P) -> bool {
+    let path = normalize(path.as_ref());
+    match resolve(&path, |source, path| if source.exists(path) { Some(()) } else { None }) {
+        Some(()) => true,
+        None if !is_virtual(&path) => fs::metadata(&path).map(|metadata| metadata.is_file()).unwrap_or(false),
+        None => false,
+    }
+}
+
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Box<Read + Send>> {
+    let path = normalize(path.as_ref());
+    let result = match resolve(&path, |source, path| if source.exists(path) { Some(source.open(path)) } else { None }) {
+        Some(result) => result,
+        None if !is_virtual(&path) => File::open(&path).map(|file| Box::new(file) as Box<Read + Send>),
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, format!("{}: No mounted source has this file", path))),
+    };
+    result.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut reader = try!(open(path));
+    try!(reader.read_to_end(&mut data));
+    Ok(data)
+}
+
+/// The file on disk `path` currently resolves to, `None` for files inside archives.
+pub fn real_path<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+    let path = normalize(path.as_ref());
+    match resolve(&path, |source, path| if source.exists(path) { Some(source.real_path(path)) } else { None }) {
+        Some(real_path) => real_path,
+        None if !is_virtual(&path) => Some(PathBuf::from(path)),
+        None => None,
+    }
+}
+
+/// The path of `name` next to the file at `path`, e.g. the image of a sprite
+/// sheet. Unlike `Path::parent` this keeps the `//` of `res://`.
+pub fn sibling<P: AsRef<Path>>(path: P, name: &str) -> PathBuf {
+    let path = normalize(path.as_ref());
+    match path.rfind('/') {
+        Some(index) => PathBuf::from(format!("{}{}", &path[..index + 1], name)),
+        None => PathBuf::from(name),
+    }
+}
+
+// Calls `f` with the sources mounted at a prefix of `path` in priority order,
+// until it returns `Some`.
+fn resolve<T, F: Fn(&Source, &str) -> Option<T>>(path: &str, f: F) -> Option<T> {
+    let mounts = MOUNTS.read().unwrap();
+    for mount in mounts.iter().filter(|mount| is_below(path, &mount.prefix)) {
+        let relative = path[mount.prefix.len()..].trim_left_matches('/');
+        if let Some(result) = f(&*mount.source, relative) {
+            return Some(result);
+        }
+    }
+    None
+}
+
+// `res://music` has `res://music/intro.ogg` below it, but not `res://musicbox/intro.ogg`.
+fn is_below(path: &str, prefix: &str) -> bool {
+    path.starts_with(prefix) &&
+    (prefix.is_empty() || prefix.ends_with('/') || path.len() == prefix.len() || path[prefix.len()..].starts_with('/'))
+}
+
+fn normalize(path: &Path) -> String {
+    format!("{}", path.display()).replace("\\", "/")
+}
+
+fn is_virtual(path: &str) -> bool {
+    path.contains("://")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{self, Cursor, Read, Write};
+    use std::path::PathBuf;
+
+    use super::{PackBuilder, Pack, Source, is_below, mount, unmount, read, exists, sibling};
+
+    // One file with fixed contents
+    struct Single(&'static str, &'static [u8]);
+
+    impl Source for Single {
+        fn exists(&self, path: &str) -> bool {
+            path == self.0
+        }
+
+        fn open(&self, _path: &str) -> io::Result<Box<Read + Send>> {
+            Ok(Box::new(Cursor::new(self.1)))
+        }
+    }
+
+    #[test]
+    fn below() {
+        assert!(is_below("res://music/intro.ogg", "res://music"));
+        assert!(is_below("res://music/intro.ogg", "res://music/"));
+        assert!(is_below("res://music", "res://music"));
+        assert!(is_below("sprites/hero.png", ""));
+        assert!(!is_below("res://musicbox/intro.ogg", "res://music"));
+        assert!(!is_below("res://music/intro.ogg", "mod://music"));
+    }
+
+    #[test]
+    fn siblings() {
+        assert_eq!(sibling("res://sprites/hero.json", "hero.png"), PathBuf::from("res://sprites/hero.png"));
+        assert_eq!(sibling("res://hero.json", "hero.png"), PathBuf::from("res://hero.png"));
+        assert_eq!(sibling("hero.json", "hero.png"), PathBuf::from("hero.png"));
+    }
+
+    // Mounts are global, each test uses its own prefix
+    #[test]
+    fn priority() {
+        mount("priority-test://", Single("a.txt", b"low"), 0);
+        mount("priority-test://", Single("a.txt", b"high"), 10);
+        mount("priority-test://", Single("b.txt", b"other"), 20);
+        assert_eq!(read("priority-test://a.txt").unwrap(), b"high".to_vec());
+        assert_eq!(read("priority-test://b.txt").unwrap(), b"other".to_vec());
+
+        // The latest among the same priority
+        mount("priority-test://", Single("a.txt", b"latest"), 10);
+        assert_eq!(read("priority-test://a.txt").unwrap(), b"latest".to_vec());
+
+        unmount("priority-test://");
+        assert!(!exists("priority-test://a.txt"));
+        assert!(read("priority-test://a.txt").is_err());
+    }
+
+    #[test]
+    fn mounted_pack() {
+        let dir = env::temp_dir().join("hammer-vfs-test-pack");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("hero.json");
+        File::create(&file).unwrap().write_all(b"{\"speed\": 10}").unwrap();
+
+        let output = dir.join("assets.hpak");
+        PackBuilder::new().add_file("sprites/hero.json", &file).write(&output).unwrap();
+        mount("pack-test://", Pack::open(&output).unwrap(), 0);
+
+        assert!(exists("pack-test://sprites/hero.json"));
+        assert_eq!(read("pack-test://sprites/hero.json").unwrap(), b"{\"speed\": 10}".to_vec());
+        assert!(read("pack-test://sprites/villain.json").is_err());
+
+        unmount("pack-test://");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
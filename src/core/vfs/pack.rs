@@ -0,0 +1,439 @@
+//! Hammer pack files, one archive with the assets of a game.
+//!
+//! All numbers are little endian.
+//!
+//! ```text
+//! header: b"HPAK", version: u32, entry count: u32, index offset: u64
+//! data:   the entries, each one stored or deflated
+//! index:  per entry: name length: u16, name, offset: u64, stored size: u64,
+//!         size: u64, compression: u8, FNV-1a hash of the content: u64
+//! ```
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::Compression as Level;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+use super::Source;
+
+const MAGIC: &'static [u8; 4] = b"HPAK";
+const VERSION: u32 = 1;
+const HEADER_SIZE: u64 = 20;
+// An index entry with an empty name
+const MIN_INDEX_ENTRY_SIZE: usize = 2 + 33;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Compression {
+    Stored,
+    Deflated,
+}
+
+impl Compression {
+    fn from_u8(value: u8) -> Option<Compression> {
+        match value {
+            0 => Some(Compression::Stored),
+            1 => Some(Compression::Deflated),
+            _ => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Compression::Stored => 0,
+            Compression::Deflated => 1,
+        }
+    }
+}
+
+struct Entry {
+    offset: u64,
+    stored_size: u64,
+    size: u64,
+    compression: Compression,
+    hash: u64,
+}
+
+pub struct Pack {
+    path: PathBuf,
+    entries: HashMap<String, Entry>,
+}
+
+impl Pack {
+    /// Reads the index, the entries are read when opened.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Pack> {
+        let path = path.as_ref();
+        let mut file = try!(File::open(path));
+        let file_size = try!(file.metadata()).len();
+
+        let mut header = [0; HEADER_SIZE as usize];
+        try!(file.read_exact(&mut header));
+        if &header[0..4] != MAGIC {
+            return Err(invalid(path, "Not a pack file"));
+        }
+        let version = read_u32(&header[4..]);
+        if version != VERSION {
+            return Err(invalid(path, &format!("Unsupported version {}", version)));
+        }
+        let entry_count = read_u32(&header[8..]) as usize;
+        let index_offset = read_u64(&header[12..]);
+        if index_offset < HEADER_SIZE || index_offset > file_size {
+            return Err(invalid(path, "The index is outside of the file"));
+        }
+
+        try!(file.seek(SeekFrom::Start(index_offset)));
+        let mut index = Vec::new();
+        try!(file.read_to_end(&mut index));
+
+        // The count may be corrupted, the index can't hold more entries than this
+        let mut entries = HashMap::with_capacity(cmp::min(entry_count, index.len() / MIN_INDEX_ENTRY_SIZE));
+        let mut cursor = Cursor::new(&index[..]);
+        for _ in 0..entry_count {
+            let mut name_len = [0; 2];
+            try!(cursor.read_exact(&mut name_len));
+            let mut name = vec![0; read_u16(&name_len) as usize];
+            try!(cursor.read_exact(&mut name));
+            let name = try!(String::from_utf8(name).map_err(|_| invalid(path, "Entry name is not UTF-8")));
+
+            let mut fields = [0; 33];
+            try!(cursor.read_exact(&mut fields));
+            let compression = match Compression::from_u8(fields[24]) {
+                Some(compression) => compression,
+                None => return Err(invalid(path, &format!("Unknown compression of {}", name))),
+            };
+
+            let entry = Entry {
+                offset: read_u64(&fields[0..]),
+                stored_size: read_u64(&fields[8..]),
+                size: read_u64(&fields[16..]),
+                compression: compression,
+                hash: read_u64(&fields[25..]),
+            };
+
+            // Checked here, so reading an entry never allocates more than the file has
+            let end = entry.offset.checked_add(entry.stored_size);
+            if entry.offset < HEADER_SIZE || end.map_or(true, |end| end > index_offset) {
+                return Err(invalid(path, &format!("{} is outside of the data", name)));
+            }
+            if entry.compression == Compression::Stored && entry.size != entry.stored_size {
+                return Err(invalid(path, &format!("{} is corrupted", name)));
+            }
+
+            entries.insert(name, entry);
+        }
+
+        info!("Opened {} with {} files", path.display(), entries.len());
+
+        Ok(Pack {
+            path: path.to_path_buf(),
+            entries: entries,
+        })
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.keys().map(|name| &name[..]).collect()
+    }
+
+    /// Hash of the uncompressed content, e.g. to tell which files a patch changes.
+    pub fn hash(&self, name: &str) -> Option<u64> {
+        self.entries.get(name).map(|entry| entry.hash)
+    }
+
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let entry = match self.entries.get(name) {
+            Some(entry) => entry,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not in {}", name, self.path.display()))),
+        };
+
+        let mut file = try!(File::open(&self.path));
+        try!(file.seek(SeekFrom::Start(entry.offset)));
+        let mut stored = vec![0; entry.stored_size as usize];
+        try!(file.read_exact(&mut stored));
+
+        let data = match entry.compression {
+            Compression::Stored => stored,
+            Compression::Deflated => {
+                // Stops one byte past the size, more is corrupted anyway
+                let mut data = Vec::new();
+                try!(DeflateDecoder::new(&stored[..]).take(entry.size.saturating_add(1)).read_to_end(&mut data));
+                data
+            }
+        };
+
+        if data.len() as u64 != entry.size || fnv1a(&data) != entry.hash {
+            return Err(invalid(&self.path, &format!("{} is corrupted", name)));
+        }
+
+        Ok(data)
+    }
+}
+
+impl Source for Pack {
+    fn exists(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    fn open(&self, path: &str) -> io::Result<Box<Read + Send>> {
+        let data = try!(self.read(path));
+        Ok(Box::new(Cursor::new(data)))
+    }
+}
+
+pub struct PackBuilder {
+    files: Vec<(String, PathBuf)>,
+}
+
+impl PackBuilder {
+    pub fn new() -> PackBuilder {
+        PackBuilder {
+            files: Vec::new(),
+        }
+    }
+
+    pub fn add_file<S: Into<String>, P: AsRef<Path>>(&mut self, name: S, path: P) -> &mut Self {
+        self.files.push((name.into(), path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Adds every file below `dir`, named `<prefix><path relative to dir>`
+    /// with `/` as separator.
+    pub fn add_dir<P: AsRef<Path>>(&mut self, dir: P, prefix: &str) -> io::Result<&mut Self> {
+        let dir = dir.as_ref();
+        let mut stack = vec![dir.to_path_buf()];
+        while let Some(current) = stack.pop() {
+            for entry in try!(fs::read_dir(&current)) {
+                let path = try!(entry).path();
+                if try!(fs::metadata(&path)).is_dir() {
+                    stack.push(path);
+                } else {
+                    let relative = path.strip_prefix(dir).unwrap();
+                    let name = format!("{}{}", prefix, relative.display()).replace("\\", "/");
+                    self.files.push((name, path));
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Writes the pack, every file is deflated unless that doesn't make it smaller.
+    /// An older version of the pack among the files, e.g. when it is written
+    /// into the directory it packs, is left out.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let output = fs::canonicalize(path).ok();
+        let mut files = self.files.iter().filter(|&&(ref name, ref file)| {
+            let is_output = output.is_some() && fs::canonicalize(file).ok() == output;
+            if is_output {
+                info!("Skipped {}, it is the pack itself", name);
+            }
+            !is_output
+        }).collect::<Vec<_>>();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut out = try!(File::create(path));
+        try!(out.write_all(MAGIC));
+        try!(out.write_all(&u32_bytes(VERSION)));
+        try!(out.write_all(&u32_bytes(files.len() as u32)));
+        // Index offset, filled in once the data is written
+        try!(out.write_all(&u64_bytes(0)));
+
+        let mut index = Vec::new();
+        let mut offset = HEADER_SIZE;
+        for &&(ref name, ref path) in files.iter() {
+            if name.len() > u16::max_value() as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Name {} is too long", name)));
+            }
+
+            let mut data = Vec::new();
+            try!(try!(File::open(path)).read_to_end(&mut data));
+
+            let mut encoder = DeflateEncoder::new(Vec::new(), Level::Best);
+            try!(encoder.write_all(&data));
+            let deflated = try!(encoder.finish());
+
+            let (compression, stored) = if deflated.len() < data.len() {
+                (Compression::Deflated, &deflated)
+            } else {
+                (Compression::Stored, &data)
+            };
+
+            try!(out.write_all(stored));
+
+            index.extend_from_slice(&u16_bytes(name.len() as u16));
+            index.extend_from_slice(name.as_bytes());
+            index.extend_from_slice(&u64_bytes(offset));
+            index.extend_from_slice(&u64_bytes(stored.len() as u64));
+            index.extend_from_slice(&u64_bytes(data.len() as u64));
+            index.push(compression.to_u8());
+            index.extend_from_slice(&u64_bytes(fnv1a(&data)));
+
+            info!("Packed {} ({} -> {} bytes)", name, data.len(), stored.len());
+
+            offset += stored.len() as u64;
+        }
+
+        try!(out.write_all(&index));
+        try!(out.seek(SeekFrom::Start(12)));
+        try!(out.write_all(&u64_bytes(offset)));
+
+        Ok(())
+    }
+}
+
+/// 64 bit FNV-1a, the content hash of the entries.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for &byte in data {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn invalid(path: &Path, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message))
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    data[0] as u16 | (data[1] as u16) << 8
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    read_u16(data) as u32 | (read_u16(&data[2..]) as u32) << 16
+}
+
+fn read_u64(data: &[u8]) -> u64 {
+    read_u32(data) as u64 | (read_u32(&data[4..]) as u64) << 32
+}
+
+fn u16_bytes(value: u16) -> [u8; 2] {
+    [value as u8, (value >> 8) as u8]
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+fn u64_bytes(value: u64) -> [u8; 8] {
+    let (low, high) = (u32_bytes(value as u32), u32_bytes((value >> 32) as u32));
+    [low[0], low[1], low[2], low[3], high[0], high[1], high[2], high[3]]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+
+    use super::{Pack, PackBuilder, fnv1a};
+
+    use vfs::Source;
+
+    // A fresh directory per test, tests run in parallel
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("hammer-pack-test-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_file(path: &PathBuf, data: &[u8]) {
+        File::create(path).unwrap().write_all(data).unwrap();
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = temp_dir("round-trip");
+        fs::create_dir_all(dir.join("sprites")).unwrap();
+
+        let text = b"hello hello hello hello hello hello hello hello".to_vec();
+        // Doesn't get smaller when deflated, so it is stored
+        let noise = (0..256u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect::<Vec<_>>();
+        write_file(&dir.join("text.txt"), &text);
+        write_file(&dir.join("sprites").join("noise.bin"), &noise);
+        write_file(&dir.join("empty"), b"");
+
+        let output = dir.join("out.hpak");
+        PackBuilder::new().add_dir(&dir, "assets/").unwrap().write(&output).unwrap();
+
+        let pack = Pack::open(&output).unwrap();
+        let mut names = pack.names();
+        names.sort();
+        assert_eq!(names, vec!["assets/empty", "assets/sprites/noise.bin", "assets/text.txt"]);
+
+        assert_eq!(pack.read("assets/text.txt").unwrap(), text);
+        assert_eq!(pack.read("assets/sprites/noise.bin").unwrap(), noise);
+        assert_eq!(pack.read("assets/empty").unwrap(), Vec::<u8>::new());
+        assert_eq!(pack.hash("assets/text.txt"), Some(fnv1a(&text)));
+        assert!(pack.read("assets/missing").is_err());
+
+        let mut data = Vec::new();
+        pack.open("assets/text.txt").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, text);
+
+        // Packing again doesn't pack the old pack
+        PackBuilder::new().add_dir(&dir, "assets/").unwrap().write(&output).unwrap();
+        assert_eq!(Pack::open(&output).unwrap().names().len(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let dir = temp_dir("corruption");
+        let file = dir.join("text.txt");
+        write_file(&file, b"abcdefgh");
+
+        let output = dir.join("out.hpak");
+        PackBuilder::new().add_file("text.txt", &file).write(&output).unwrap();
+
+        // The content is stored right after the 20 byte header
+        let mut data = Vec::new();
+        File::open(&output).unwrap().read_to_end(&mut data).unwrap();
+        data[20] ^= 0xff;
+        write_file(&output, &data);
+
+        assert!(Pack::open(&output).unwrap().read("text.txt").is_err());
+
+        write_file(&output, b"not a pack file at all");
+        assert!(Pack::open(&output).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_sizes_past_the_file() {
+        let dir = temp_dir("sizes");
+        let file = dir.join("text.txt");
+        write_file(&file, b"abcdefgh");
+
+        let output = dir.join("out.hpak");
+        PackBuilder::new().add_file("text.txt", &file).write(&output).unwrap();
+
+        // The index follows the 8 bytes of data, the stored size follows the
+        // name length, the name and the offset
+        let mut data = Vec::new();
+        File::open(&output).unwrap().read_to_end(&mut data).unwrap();
+        let stored_size = 20 + 8 + 2 + "text.txt".len() + 8;
+        for byte in data[stored_size..stored_size + 8].iter_mut() {
+            *byte = 0xff;
+        }
+        write_file(&output, &data);
+        assert!(Pack::open(&output).is_err());
+
+        // An index offset past the end
+        let mut data = Vec::new();
+        PackBuilder::new().add_file("text.txt", &file).write(&output).unwrap();
+        File::open(&output).unwrap().read_to_end(&mut data).unwrap();
+        data[19] = 0x7f;
+        write_file(&output, &data);
+        assert!(Pack::open(&output).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
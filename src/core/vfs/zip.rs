@@ -0,0 +1,302 @@
+//! Read only zip archives, entries must be stored or deflated.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use flate2::read::DeflateDecoder;
+
+use super::Source;
+
+use Error;
+
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x02014b50;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE: usize = 20;
+const CENTRAL_DIRECTORY_HEADER_SIZE: usize = 46;
+const LOCAL_HEADER_SIZE: usize = 30;
+const MAX_COMMENT_SIZE: usize = 0xffff;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+struct Entry {
+    method: u16,
+    compressed_size: u64,
+    local_header_offset: u64,
+}
+
+pub struct Zip {
+    path: PathBuf,
+    size: u64,
+    entries: HashMap<String, Entry>,
+}
+
+impl Zip {
+    /// Reads the central directory, the entries are read when opened. Zip64
+    /// archives, i.e. over 4 GB or 65535 files, are not supported.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Zip, Error> {
+        let path = path.as_ref();
+        let mut file = try!(File::open(path));
+        let file_size = try!(file.seek(SeekFrom::End(0)));
+
+        // The end of central directory record is followed by a comment of unknown size
+        let tail_size = cmp::min(file_size, (END_OF_CENTRAL_DIRECTORY_SIZE + MAX_COMMENT_SIZE) as u64);
+        try!(file.seek(SeekFrom::Start(file_size - tail_size)));
+        let mut tail = vec![0; tail_size as usize];
+        try!(file.read_exact(&mut tail));
+
+        let eocd = match (0..tail.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE - 1)).rev()
+                                                    .find(|&i| read_u32(&tail, i) == END_OF_CENTRAL_DIRECTORY_SIGNATURE) {
+            Some(eocd) => eocd,
+            None => return Err(decode(path, "Missing end of central directory")),
+        };
+
+        let entry_count = read_u16(&tail, eocd + 10) as usize;
+        let directory_size = read_u32(&tail, eocd + 12) as usize;
+        let directory_offset = read_u32(&tail, eocd + 16) as u64;
+
+        // Zip64 archives put a locator before the record and saturate its fields
+        let has_locator = eocd >= ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE &&
+                          read_u32(&tail, eocd - ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE) == ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE;
+        if has_locator || entry_count == 0xffff || directory_size == 0xffffffff || directory_offset == 0xffffffff {
+            return Err(decode(path, "Zip64 archives are not supported"));
+        }
+
+        // Checked before allocating anything of these sizes
+        if directory_offset + directory_size as u64 > file_size {
+            return Err(decode(path, "The central directory is outside of the file"));
+        }
+
+        try!(file.seek(SeekFrom::Start(directory_offset)));
+        let mut directory = vec![0; directory_size];
+        try!(file.read_exact(&mut directory));
+
+        let mut entries = HashMap::with_capacity(entry_count);
+        let mut offset = 0;
+        for _ in 0..entry_count {
+            if offset + CENTRAL_DIRECTORY_HEADER_SIZE > directory.len() ||
+               read_u32(&directory, offset) != CENTRAL_DIRECTORY_SIGNATURE {
+                return Err(decode(path, "Corrupted central directory"));
+            }
+
+            let method = read_u16(&directory, offset + 10);
+            let compressed_size = read_u32(&directory, offset + 20) as u64;
+            let name_len = read_u16(&directory, offset + 28) as usize;
+            let extra_len = read_u16(&directory, offset + 30) as usize;
+            let comment_len = read_u16(&directory, offset + 32) as usize;
+            let local_header_offset = read_u32(&directory, offset + 42) as u64;
+
+            if compressed_size == 0xffffffff || local_header_offset == 0xffffffff {
+                return Err(decode(path, "Zip64 archives are not supported"));
+            }
+            if local_header_offset + LOCAL_HEADER_SIZE as u64 + compressed_size > file_size {
+                return Err(decode(path, "An entry is outside of the file"));
+            }
+
+            let name_start = offset + CENTRAL_DIRECTORY_HEADER_SIZE;
+            if name_start + name_len > directory.len() {
+                return Err(decode(path, "Corrupted central directory"));
+            }
+            let name = String::from_utf8_lossy(&directory[name_start..name_start + name_len]).into_owned();
+
+            // Directories are only implied by the file names
+            if !name.ends_with('/') {
+                entries.insert(name, Entry {
+                    method: method,
+                    compressed_size: compressed_size,
+                    local_header_offset: local_header_offset,
+                });
+            }
+
+            offset = name_start + name_len + extra_len + comment_len;
+        }
+
+        info!("Opened {} with {} files", path.display(), entries.len());
+
+        Ok(Zip {
+            path: path.to_path_buf(),
+            size: file_size,
+            entries: entries,
+        })
+    }
+}
+
+impl Source for Zip {
+    fn exists(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    fn open(&self, path: &str) -> io::Result<Box<Read + Send>> {
+        let entry = match self.entries.get(path) {
+            Some(entry) => entry,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not in {}", path, self.path.display()))),
+        };
+
+        let mut file = try!(File::open(&self.path));
+        try!(file.seek(SeekFrom::Start(entry.local_header_offset)));
+
+        let mut header = [0; LOCAL_HEADER_SIZE];
+        try!(file.read_exact(&mut header));
+        if read_u32(&header, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid(&self.path, "Corrupted local file header"));
+        }
+
+        // The local extra field may differ from the one in the central directory
+        let name_len = read_u16(&header, 26) as i64;
+        let extra_len = read_u16(&header, 28) as i64;
+        let start = try!(file.seek(SeekFrom::Current(name_len + extra_len)));
+        if start + entry.compressed_size > self.size {
+            return Err(invalid(&self.path, &format!("{} is outside of the file", path)));
+        }
+
+        let mut data = vec![0; entry.compressed_size as usize];
+        try!(file.read_exact(&mut data));
+
+        match entry.method {
+            METHOD_STORED => Ok(Box::new(Cursor::new(data))),
+            METHOD_DEFLATED => Ok(Box::new(DeflateDecoder::new(Cursor::new(data)))),
+            method => Err(invalid(&self.path, &format!("Unsupported compression method {} of {}", method, path))),
+        }
+    }
+}
+
+fn decode(path: &Path, message: &str) -> Error {
+    Error::Decode(format!("{}: {}", path.display(), message))
+}
+
+fn invalid(path: &Path, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    data[offset] as u16 | (data[offset + 1] as u16) << 8
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    read_u16(data, offset) as u32 | (read_u16(data, offset + 2) as u32) << 16
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+
+    use flate2::Compression as Level;
+    use flate2::write::DeflateEncoder;
+
+    use vfs::Source;
+
+    use super::{Zip, METHOD_STORED, METHOD_DEFLATED};
+
+    fn push(data: &mut Vec<u8>, value: u64, bytes: usize) {
+        for i in 0..bytes {
+            data.push((value >> (i * 8)) as u8);
+        }
+    }
+
+    // A zip of (name, method, stored data, size), the CRCs aren't checked
+    fn zip(entries: &[(&str, u16, Vec<u8>, usize)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut directory = Vec::new();
+        for &(name, method, ref stored, size) in entries {
+            let offset = data.len() as u64;
+            push(&mut data, 0x04034b50, 4);
+            push(&mut data, 20, 2);
+            push(&mut data, 0, 2);
+            push(&mut data, method as u64, 2);
+            push(&mut data, 0, 8);
+            push(&mut data, stored.len() as u64, 4);
+            push(&mut data, size as u64, 4);
+            push(&mut data, name.len() as u64, 2);
+            push(&mut data, 0, 2);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(stored);
+
+            push(&mut directory, 0x02014b50, 4);
+            push(&mut directory, 20, 2);
+            push(&mut directory, 20, 2);
+            push(&mut directory, 0, 2);
+            push(&mut directory, method as u64, 2);
+            push(&mut directory, 0, 8);
+            push(&mut directory, stored.len() as u64, 4);
+            push(&mut directory, size as u64, 4);
+            push(&mut directory, name.len() as u64, 2);
+            directory.extend_from_slice(&[0; 12]);
+            push(&mut directory, offset, 4);
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let directory_offset = data.len() as u64;
+        data.extend_from_slice(&directory);
+        push(&mut data, 0x06054b50, 4);
+        push(&mut data, 0, 4);
+        push(&mut data, entries.len() as u64, 2);
+        push(&mut data, entries.len() as u64, 2);
+        push(&mut data, directory.len() as u64, 4);
+        push(&mut data, directory_offset, 4);
+        push(&mut data, 0, 2);
+        data
+    }
+
+    fn write_zip(name: &str, data: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("hammer-zip-test-{}.zip", name));
+        File::create(&path).unwrap().write_all(data).unwrap();
+        path
+    }
+
+    fn read(zip: &Zip, name: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        zip.open(name).unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn reads_stored_and_deflated() {
+        let text = b"hello hello hello hello".to_vec();
+        let mut encoder = DeflateEncoder::new(Vec::new(), Level::Best);
+        encoder.write_all(&text).unwrap();
+        let deflated = encoder.finish().unwrap();
+
+        let path = write_zip("read", &zip(&[("a.txt", METHOD_STORED, b"abc".to_vec(), 3),
+                                            ("dir/", METHOD_STORED, Vec::new(), 0),
+                                            ("dir/b.txt", METHOD_DEFLATED, deflated, text.len())]));
+        let zip = Zip::open(&path).unwrap();
+
+        assert!(zip.exists("a.txt"));
+        assert!(zip.exists("dir/b.txt"));
+        assert!(!zip.exists("dir/"));
+        assert!(zip.open("missing").is_err());
+        assert_eq!(read(&zip, "a.txt"), b"abc".to_vec());
+        assert_eq!(read(&zip, "dir/b.txt"), text);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_sizes_past_the_file() {
+        let mut data = zip(&[("a.txt", METHOD_STORED, b"abc".to_vec(), 3)]);
+        // The compressed size in the central directory, after the local header and data
+        let size = 30 + 5 + 3 + 20;
+        data[size + 3] = 0x7f;
+        let path = write_zip("sizes", &data);
+        assert!(Zip::open(&path).is_err());
+
+        // The central directory size in the end of central directory record
+        let mut data = zip(&[("a.txt", METHOD_STORED, b"abc".to_vec(), 3)]);
+        let len = data.len();
+        data[len - 22 + 15] = 0x7f;
+        let path = write_zip("sizes", &data);
+        assert!(Zip::open(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,2 +1,144 @@
+#[macro_use]
+extern crate log;
+
 pub extern crate hammer_core as core;
 pub extern crate hammer_window as window;
+
+use std::default::Default;
+use std::mem;
+use std::time::Instant;
+
+use core::Key;
+use core::math::Scalar;
+use core::asset;
+use core::input;
+use core::input::keyboard;
+use core::renderer;
+
+use window::{Window, WindowBuilder};
+use window::event::{self, Event};
+
+pub trait Game {
+    fn update();
+
+    /// Called once per frame after the updates, push drawables here.
+    fn render() {}
+}
+
+pub struct WindowConfig {
+    pub title: String,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Default for WindowConfig {
+    fn default() -> WindowConfig {
+        WindowConfig {
+            title: "Hammer".to_string(),
+            width: 800,
+            height: 600,
+        }
+    }
+}
+
+pub struct DebugConfig {
+    pub is_exit_on_esc: bool,
+    /// Reload assets when their files change, see `asset::enable_hot_reload`.
+    pub is_hot_reload: bool,
+}
+
+impl Default for DebugConfig {
+    fn default() -> DebugConfig {
+        DebugConfig {
+            is_exit_on_esc: false,
+            is_hot_reload: false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Config {
+    pub window: WindowConfig,
+    pub debug: DebugConfig,
+}
+
+// Never catch up more than this amount of time in one frame, otherwise a
+// long stall (e.g. dragging the window) makes the game update forever.
+const MAX_FRAME_TIME: Scalar = 0.25;
+
+pub fn run<G: Game>(config: Config) {
+    let window = WindowBuilder::new()
+                     .title(&config.window.title)
+                     .size(config.window.width, config.window.height)
+                     .build()
+                     .expect("Failed to create window");
+    run_with_window::<G>(config, window);
+}
+
+/// Runs the game in a window of the caller, e.g. a headless one whose
+/// `event_sender` drives the game in tests. `config.window` is ignored.
+pub fn run_with_window<G: Game>(config: Config, mut window: Window) {
+    window.show();
+
+    renderer::set_target(&window);
+
+    if config.debug.is_hot_reload {
+        if let Err(e) = asset::enable_hot_reload() {
+            error!("Failed to enable hot reloading: {}", e);
+        }
+    }
+
+    let delta = input::delta();
+    let mut accumulator = 0.0;
+    let mut last = Instant::now();
+
+    'main_loop: loop {
+        for event in window.poll_events() {
+            match event {
+                Event::Close => break 'main_loop,
+                Event::KeyDown(key) => keyboard::set_down(to_key(key)),
+                Event::KeyUp(key) => keyboard::set_up(to_key(key)),
+                Event::Resize { w, h, .. } => renderer::resize(w, h),
+                _ => {}
+            }
+        }
+
+        if config.debug.is_hot_reload {
+            asset::reload_changed();
+        }
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(last);
+        last = now;
+
+        accumulator += elapsed.as_secs() as Scalar + elapsed.subsec_nanos() as Scalar * 1e-9;
+        if accumulator > MAX_FRAME_TIME {
+            accumulator = MAX_FRAME_TIME;
+        }
+
+        // Fixed time step, `keyboard::update` is called after every step so
+        // that `keyboard::press` is only true for one update.
+        while accumulator >= delta {
+            if config.debug.is_exit_on_esc && keyboard::press(Key::Escape) {
+                break 'main_loop;
+            }
+
+            G::update();
+            keyboard::update();
+
+            accumulator -= delta;
+        }
+
+        renderer::clear(0.0, 0.0, 0.0, 1.0);
+        G::render();
+        renderer::present();
+    }
+
+    renderer::release();
+    window.close();
+}
+
+fn to_key(key: event::Key) -> Key {
+    // Both key enums are copied from SDL so they share the same values.
+    unsafe { mem::transmute(key as u16) }
+}
@@ -0,0 +1,300 @@
+extern crate osmesa_sys;
+
+use std::cell::{Cell, RefCell};
+use std::ffi::CString;
+use std::path::Path;
+use std::ptr;
+use std::rc::Rc;
+use std::sync::*;
+use std::sync::mpsc::*;
+
+use std::os::raw::{c_int, c_void};
+
+use self::osmesa_sys::*;
+
+use event::Event;
+
+use Error;
+
+const DEFAULT_WIDTH: i32 = 800;
+const DEFAULT_HEIGHT: i32 = 600;
+
+const GL_UNSIGNED_BYTE: u32 = 0x1401;
+
+#[derive(Debug)]
+pub struct WindowBuilder {
+    title: String,
+    x: Option<i32>,
+    y: Option<i32>,
+    w: Option<i32>,
+    h: Option<i32>,
+}
+
+impl WindowBuilder {
+    pub fn new() -> WindowBuilder {
+        WindowBuilder {
+            title: "Untitled".to_string(),
+            x: None,
+            y: None,
+            w: None,
+            h: None,
+        }
+    }
+
+    pub fn title(&mut self, title: &str) -> &mut Self {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn pos(&mut self, x: i32, y: i32) -> &mut Self {
+        self.x = Some(x);
+        self.y = Some(y);
+        self
+    }
+
+    pub fn size(&mut self, w: i32, h: i32) -> &mut Self {
+        self.w = Some(w);
+        self.h = Some(h);
+        self
+    }
+
+    pub fn build(&self) -> Result<Window, Error> {
+        let (event_tx, event_rx) = channel();
+
+        let w = self.w.unwrap_or(DEFAULT_WIDTH);
+        let h = self.h.unwrap_or(DEFAULT_HEIGHT);
+
+        info!("Created headless window {} ({}x{})", self.title, w, h);
+
+        Ok(Window {
+            event_tx: event_tx,
+            event_rx: event_rx,
+            shared_size: Rc::new(Cell::new((w, h))),
+
+            x: self.x.unwrap_or(0),
+            y: self.y.unwrap_or(0),
+            w: w,
+            h: h,
+        })
+    }
+}
+
+/// A window without any display server behind it.
+///
+/// Events only come from `event_sender`/`send_event`, so tests can drive the
+/// game loop with synthetic input, e.g. through `hammer::run_with_window`.
+pub struct Window {
+    event_tx: Sender<Event>,
+    event_rx: Receiver<Event>,
+    shared_size: Rc<Cell<(i32, i32)>>,
+
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+impl Window {
+    pub fn show(&mut self) {
+    }
+
+    pub fn poll_events(&mut self) -> PollEventIter {
+        PollEventIter {
+            window: self,
+        }
+    }
+
+    pub fn wait_events(&mut self) -> WaitEventIter {
+        WaitEventIter {
+            window: self,
+        }
+    }
+
+    pub fn close(self) {
+        drop(self);
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.w, self.h)
+    }
+
+    /// Returns a sender which can inject events from any thread.
+    pub fn event_sender(&self) -> Sender<Event> {
+        self.event_tx.clone()
+    }
+
+    pub fn send_event(&self, event: Event) {
+        self.event_tx.send(event).unwrap();
+    }
+
+    /// The returned context renders into memory which is resized to follow
+    /// the `Event::Resize` events of this window.
+    pub fn create_gl_context(&self) -> Result<GlContext, Error> {
+        unsafe {
+            let osmesa = try!(osmesa());
+
+            let attributes = [
+                OSMESA_FORMAT, OSMESA_RGBA as c_int,
+                OSMESA_DEPTH_BITS, 24,
+                OSMESA_STENCIL_BITS, 8,
+                OSMESA_PROFILE, OSMESA_CORE_PROFILE,
+                OSMESA_CONTEXT_MAJOR_VERSION, 3,
+                OSMESA_CONTEXT_MINOR_VERSION, 3,
+                0,
+            ];
+            let context = (osmesa.OSMesaCreateContextAttribs)(attributes.as_ptr(), ptr::null_mut());
+            if context.is_null() {
+                return Err(Error::WindowCreation("Failed to create OSMesa context".to_string()));
+            }
+
+            let finish = CString::new("glFinish").unwrap();
+            let finish = (osmesa.OSMesaGetProcAddress)(finish.as_ptr());
+
+            Ok(GlContext {
+                osmesa: osmesa,
+                context: context,
+                finish: finish,
+                shared_size: self.shared_size.clone(),
+                size: (0, 0),
+                buffer: Vec::new(),
+            })
+        }
+    }
+
+    fn handle_event(&mut self, event: &Event) {
+        match event {
+            &Event::Resize { x, y, w, h } => {
+                self.x = x;
+                self.y = y;
+                self.w = w;
+                self.h = h;
+                self.shared_size.set((w, h));
+            }
+            _ => {}
+        }
+    }
+}
+
+pub struct PollEventIter<'a> {
+    window: &'a mut Window,
+}
+
+impl<'a> Iterator for PollEventIter<'a> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.window.event_rx.try_recv().ok();
+        if let Some(ref event) = event {
+            self.window.handle_event(event);
+        }
+        event
+    }
+}
+
+pub struct WaitEventIter<'a> {
+    window: &'a mut Window,
+}
+
+impl<'a> Iterator for WaitEventIter<'a> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.window.event_rx.recv().ok();
+        if let Some(ref event) = event {
+            self.window.handle_event(event);
+        }
+        event
+    }
+}
+
+thread_local!(static THREAD_CURRENT_CONTEXT: RefCell<OSMesaContext> = RefCell::new(ptr::null_mut()));
+
+pub struct GlContext {
+    osmesa: &'static OsMesa,
+    context: OSMesaContext,
+    // Looked up once, it runs every frame
+    finish: Option<unsafe extern "C" fn()>,
+    shared_size: Rc<Cell<(i32, i32)>>,
+    size: (i32, i32),
+    buffer: Vec<u8>,
+}
+
+impl GlContext {
+    // One thread can only have one renderer be _current_.
+    pub fn make_current(&mut self) {
+        let size = self.shared_size.get();
+        let resized = size != self.size;
+
+        if resized {
+            let (w, h) = size;
+            self.buffer = vec![0; (w * h * 4) as usize];
+            self.size = size;
+        }
+
+        let osmesa = self.osmesa;
+        let context = self.context;
+        let (w, h) = self.size;
+        let buffer = self.buffer.as_mut_ptr() as *mut c_void;
+
+        THREAD_CURRENT_CONTEXT.with(|thread_current_context| {
+            let mut thread_current_context = thread_current_context.borrow_mut();
+            // The buffer is bound by OSMesaMakeCurrent, so it has to be rebound after resizing
+            if resized || *thread_current_context != context {
+                unsafe { (osmesa.OSMesaMakeCurrent)(context, buffer, GL_UNSIGNED_BYTE, w, h); }
+                *thread_current_context = context;
+            }
+        });
+    }
+
+    pub fn load_function(&self, symbol: &str) -> *const c_void {
+        unsafe {
+            let cstr = CString::new(symbol).unwrap();
+            match (self.osmesa.OSMesaGetProcAddress)(cstr.as_ptr()) {
+                Some(f) => f as *const c_void,
+                None => ptr::null(),
+            }
+        }
+    }
+
+    pub fn swap_buffers(&mut self) {
+        // There is no front buffer, just make sure the frame is in memory.
+        if let Some(finish) = self.finish {
+            unsafe { finish(); }
+        }
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        self.size
+    }
+}
+
+impl Drop for GlContext {
+    fn drop(&mut self) {
+        THREAD_CURRENT_CONTEXT.with(|thread_current_context| {
+            let mut thread_current_context = thread_current_context.borrow_mut();
+            if *thread_current_context == self.context {
+                *thread_current_context = ptr::null_mut();
+            }
+        });
+
+        unsafe { (self.osmesa.OSMesaDestroyContext)(self.context); }
+    }
+}
+
+static OSMESA_LIB_INIT: Once = ONCE_INIT;
+static mut OSMESA_LIB: *const OsMesa = 0 as *const OsMesa;
+static mut OSMESA_LIB_ERROR: Option<String> = None;
+
+unsafe fn osmesa() -> Result<&'static OsMesa, Error> {
+    OSMESA_LIB_INIT.call_once(|| {
+        match OsMesa::open(Path::new("libOSMesa.so")) {
+            Ok(lib) => OSMESA_LIB = Box::into_raw(Box::new(lib)),
+            Err(e) => OSMESA_LIB_ERROR = Some(format!("Failed to load libOSMesa.so: {:?}", e)),
+        }
+    });
+
+    match OSMESA_LIB_ERROR {
+        Some(ref reason) => Err(Error::WindowCreation(reason.clone())),
+        None => Ok(&*OSMESA_LIB),
+    }
+}
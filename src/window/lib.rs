@@ -1,10 +1,44 @@
 #[macro_use]
 extern crate log;
 
+use std::error;
+use std::fmt;
+
 pub mod event;
 
+#[cfg(all(windows, not(feature = "headless")))]
 pub mod windows;
+#[cfg(all(windows, not(feature = "headless")))]
 pub use windows::*;
 
-// TODO: Add concrete error type
-pub type Error = ();
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
+pub mod x11;
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
+pub use x11::*;
+
+#[cfg(feature = "headless")]
+pub mod headless;
+#[cfg(feature = "headless")]
+pub use headless::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The window or its GL context couldn't be created, with the reason.
+    WindowCreation(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WindowCreation(ref reason) => write!(f, "Failed to create window: {}", reason),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::WindowCreation(_) => "failed to create window",
+        }
+    }
+}
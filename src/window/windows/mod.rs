@@ -141,13 +141,18 @@ impl Window {
         (self.w, self.h)
     }
 
-    pub fn create_gl_context(&self) -> GlContext {
+    pub fn create_gl_context(&self) -> Result<GlContext, Error> {
         unsafe {
             let hdc = self.hdc();
-            GlContext {
-                hglrc: create_gl_context(hdc),
-                hdc: hdc,
+            let hglrc = create_gl_context(hdc);
+            if hglrc.is_null() {
+                return Err(Error::WindowCreation(format!("wglCreateContext failed with error {}", GetLastError())));
             }
+
+            Ok(GlContext {
+                hglrc: hglrc,
+                hdc: hdc,
+            })
         }
     }
 
@@ -452,7 +457,7 @@ fn window_proc(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> LRESULT
     return 0;
 }
 
-unsafe fn create_window(hinstance: HINSTANCE, class_name: &Vec<u16>, builder: &WindowBuilder) -> Result<Window, ()> {
+unsafe fn create_window(hinstance: HINSTANCE, class_name: &Vec<u16>, builder: &WindowBuilder) -> Result<Window, Error> {
     let (event_tx, event_rx) = channel();
 
     let state = Box::into_raw(Box::new(WindowState::new(event_tx)));
@@ -480,7 +485,7 @@ unsafe fn create_window(hinstance: HINSTANCE, class_name: &Vec<u16>, builder: &W
         (CW_USEDEFAULT, CW_USEDEFAULT)
     };
 
-    CreateWindowExW(
+    let hwnd = CreateWindowExW(
         ex_style,
         class_name.as_ptr(),
         title.as_ptr(),
@@ -492,6 +497,11 @@ unsafe fn create_window(hinstance: HINSTANCE, class_name: &Vec<u16>, builder: &W
         state as LPVOID,
     );
 
+    if hwnd.is_null() {
+        drop(Box::from_raw(state));
+        return Err(Error::WindowCreation(format!("CreateWindowExW failed with error {}", GetLastError())));
+    }
+
     let window = Window {
         event_rx: event_rx,
         state: state,
@@ -0,0 +1,392 @@
+extern crate x11;
+
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::mem;
+use std::ptr;
+use std::rc::Rc;
+use std::sync::*;
+
+use std::os::raw::{c_char, c_uint, c_ulong, c_void};
+
+use self::x11::xlib::*;
+use self::x11::glx::*;
+use self::x11::keysym::*;
+
+use event::{Event, Key};
+
+use Error;
+
+const DEFAULT_WIDTH: i32 = 800;
+const DEFAULT_HEIGHT: i32 = 600;
+
+#[derive(Debug)]
+pub struct WindowBuilder {
+    title: String,
+    x: Option<i32>,
+    y: Option<i32>,
+    w: Option<i32>,
+    h: Option<i32>,
+}
+
+impl WindowBuilder {
+    pub fn new() -> WindowBuilder {
+        WindowBuilder {
+            title: "Untitled".to_string(),
+            x: None,
+            y: None,
+            w: None,
+            h: None,
+        }
+    }
+
+    pub fn title(&mut self, title: &str) -> &mut Self {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn pos(&mut self, x: i32, y: i32) -> &mut Self {
+        self.x = Some(x);
+        self.y = Some(y);
+        self
+    }
+
+    pub fn size(&mut self, w: i32, h: i32) -> &mut Self {
+        self.w = Some(w);
+        self.h = Some(h);
+        self
+    }
+
+    pub fn build(&self) -> Result<Window, Error> {
+        // Every window opens its own display connection, but windows can
+        // live on different threads so Xlib must be told to lock itself.
+        XLIB_INIT.call_once(|| {
+            unsafe { XInitThreads(); }
+        });
+
+        unsafe { create_window(self) }
+    }
+}
+
+static XLIB_INIT: Once = ONCE_INIT;
+
+// Closes the connection once the window and all of its GL contexts are gone
+struct XDisplay(*mut Display);
+
+impl Drop for XDisplay {
+    fn drop(&mut self) {
+        unsafe { XCloseDisplay(self.0); }
+    }
+}
+
+pub struct Window {
+    display: Rc<XDisplay>,
+    window: c_ulong,
+    visual: *mut XVisualInfo,
+    colormap: Colormap,
+    wm_delete_window: Atom,
+
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+impl Window {
+    pub fn show(&mut self) {
+        unsafe {
+            XMapWindow(self.display.0, self.window);
+            XFlush(self.display.0);
+        }
+    }
+
+    pub fn poll_events(&mut self) -> PollEventIter {
+        PollEventIter {
+            window: self,
+        }
+    }
+
+    pub fn wait_events(&mut self) -> WaitEventIter {
+        WaitEventIter {
+            window: self,
+        }
+    }
+
+    pub fn close(self) {
+        drop(self);
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.w, self.h)
+    }
+
+    /// The returned context shares the display connection of this window,
+    /// which stays open until both are dropped.
+    pub fn create_gl_context(&self) -> Result<GlContext, Error> {
+        let context = unsafe { glXCreateContext(self.display.0, self.visual, ptr::null_mut(), True) };
+        if context.is_null() {
+            return Err(Error::WindowCreation("Failed to create GLX context".to_string()));
+        }
+
+        Ok(GlContext {
+            display: self.display.clone(),
+            drawable: self.window,
+            context: context,
+        })
+    }
+
+    fn handle_event(&mut self, event: &Event) {
+        match event {
+            &Event::Resize { x, y, w, h } => {
+                self.x = x;
+                self.y = y;
+                self.w = w;
+                self.h = h;
+            }
+            _ => {}
+        }
+    }
+
+    unsafe fn next_xevent(&self) -> XEvent {
+        let mut xevent = mem::uninitialized();
+        XNextEvent(self.display.0, &mut xevent);
+        xevent
+    }
+
+    fn translate_event(&self, xevent: &XEvent) -> Option<Event> {
+        match xevent.get_type() {
+            ConfigureNotify => {
+                let configure = XConfigureEvent::from(xevent);
+                // X11 also notifies moves, only report real size changes like WM_SIZE does.
+                if configure.width != self.w || configure.height != self.h {
+                    Some(Event::Resize {
+                        x: configure.x,
+                        y: configure.y,
+                        w: configure.width,
+                        h: configure.height,
+                    })
+                } else {
+                    None
+                }
+            }
+
+            ClientMessage => {
+                let client_message = XClientMessageEvent::from(xevent);
+                if client_message.data.get_long(0) as Atom == self.wm_delete_window {
+                    Some(Event::Close)
+                } else {
+                    None
+                }
+            }
+
+            KeyPress => {
+                let mut key_event = XKeyEvent::from(xevent);
+                let keysym = unsafe { XLookupKeysym(&mut key_event, 0) };
+                Some(Event::KeyDown(keysym_to_key(keysym as c_uint)))
+            }
+
+            KeyRelease => {
+                let mut key_event = XKeyEvent::from(xevent);
+                let keysym = unsafe { XLookupKeysym(&mut key_event, 0) };
+                Some(Event::KeyUp(keysym_to_key(keysym as c_uint)))
+            }
+
+            _ => None,
+        }
+    }
+}
+
+impl Drop for Window {
+    fn drop(&mut self) {
+        unsafe {
+            XDestroyWindow(self.display.0, self.window);
+            XFreeColormap(self.display.0, self.colormap);
+            XFree(self.visual as *mut c_void);
+        }
+    }
+}
+
+pub struct PollEventIter<'a> {
+    window: &'a mut Window,
+}
+
+impl<'a> Iterator for PollEventIter<'a> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            while XPending(self.window.display.0) > 0 {
+                let xevent = self.window.next_xevent();
+                if let Some(event) = self.window.translate_event(&xevent) {
+                    self.window.handle_event(&event);
+                    return Some(event);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+pub struct WaitEventIter<'a> {
+    window: &'a mut Window,
+}
+
+impl<'a> Iterator for WaitEventIter<'a> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let xevent = unsafe { self.window.next_xevent() };
+            if let Some(event) = self.window.translate_event(&xevent) {
+                self.window.handle_event(&event);
+                return Some(event);
+            }
+        }
+    }
+}
+
+thread_local!(static THREAD_CURRENT_CONTEXT: RefCell<GLXContext> = RefCell::new(ptr::null_mut()));
+
+pub struct GlContext {
+    display: Rc<XDisplay>,
+    drawable: GLXDrawable,
+    context: GLXContext,
+}
+
+impl GlContext {
+    // One thread can only have one renderer be _current_.
+    pub fn make_current(&mut self) {
+        THREAD_CURRENT_CONTEXT.with(|thread_current_context| {
+            let mut thread_current_context = thread_current_context.borrow_mut();
+            if *thread_current_context != self.context {
+                unsafe { glXMakeCurrent(self.display.0, self.drawable, self.context); }
+                info!("Thread {:?} has changed current glx context from {:?} to {:?}",
+                      ::std::thread::current().name(), *thread_current_context, self.context);
+                *thread_current_context = self.context;
+            }
+        });
+    }
+
+    pub fn load_function(&self, symbol: &str) -> *const c_void {
+        unsafe {
+            let cstr = CString::new(symbol).unwrap();
+            match glXGetProcAddress(cstr.as_ptr() as *const u8) {
+                Some(f) => f as *const c_void,
+                None => ptr::null(),
+            }
+        }
+    }
+
+    pub fn swap_buffers(&mut self) {
+        unsafe { glXSwapBuffers(self.display.0, self.drawable); }
+    }
+}
+
+impl Drop for GlContext {
+    fn drop(&mut self) {
+        THREAD_CURRENT_CONTEXT.with(|thread_current_context| {
+            let mut thread_current_context = thread_current_context.borrow_mut();
+            if *thread_current_context == self.context {
+                unsafe { glXMakeCurrent(self.display.0, 0, ptr::null_mut()); }
+                *thread_current_context = ptr::null_mut();
+            }
+        });
+
+        unsafe { glXDestroyContext(self.display.0, self.context); }
+    }
+}
+
+unsafe fn create_window(builder: &WindowBuilder) -> Result<Window, Error> {
+    let display = XOpenDisplay(ptr::null());
+    if display.is_null() {
+        return Err(Error::WindowCreation("Failed to open X display".to_string()));
+    }
+
+    let screen = XDefaultScreen(display);
+    let root = XRootWindow(display, screen);
+
+    let mut attributes = [
+        GLX_RGBA,
+        GLX_DOUBLEBUFFER,
+        GLX_RED_SIZE, 8,
+        GLX_GREEN_SIZE, 8,
+        GLX_BLUE_SIZE, 8,
+        GLX_ALPHA_SIZE, 8,
+        GLX_DEPTH_SIZE, 24,
+        GLX_STENCIL_SIZE, 8,
+        0,
+    ];
+    let visual = glXChooseVisual(display, screen, attributes.as_mut_ptr());
+    if visual.is_null() {
+        XCloseDisplay(display);
+        return Err(Error::WindowCreation("Failed to choose a GLX visual".to_string()));
+    }
+
+    let colormap = XCreateColormap(display, root, (*visual).visual, AllocNone);
+
+    let mut swa: XSetWindowAttributes = mem::zeroed();
+    swa.colormap = colormap;
+    swa.event_mask = StructureNotifyMask | KeyPressMask | KeyReleaseMask;
+
+    let x = builder.x.unwrap_or(0);
+    let y = builder.y.unwrap_or(0);
+    let w = builder.w.unwrap_or(DEFAULT_WIDTH);
+    let h = builder.h.unwrap_or(DEFAULT_HEIGHT);
+
+    let window = XCreateWindow(display, root,
+                               x, y, w as c_uint, h as c_uint, 0,
+                               (*visual).depth, InputOutput as c_uint, (*visual).visual,
+                               CWColormap | CWEventMask, &mut swa);
+
+    // Window managers ignore the position passed to XCreateWindow unless it is hinted
+    if builder.x.is_some() && builder.y.is_some() {
+        let hints = XAllocSizeHints();
+        (*hints).flags = USPosition | PPosition;
+        (*hints).x = x;
+        (*hints).y = y;
+        XSetWMNormalHints(display, window, hints);
+        XFree(hints as *mut c_void);
+    }
+
+    let title = CString::new(&*builder.title).unwrap();
+    XStoreName(display, window, title.as_ptr());
+
+    let mut wm_delete_window = XInternAtom(display, b"WM_DELETE_WINDOW\0".as_ptr() as *const c_char, False);
+    XSetWMProtocols(display, window, &mut wm_delete_window, 1);
+
+    // Don't send KeyRelease for auto repeated keys, which matches WM_KEYDOWN/WM_KEYUP
+    XkbSetDetectableAutoRepeat(display, True, ptr::null_mut());
+
+    Ok(Window {
+        display: Rc::new(XDisplay(display)),
+        window: window,
+        visual: visual,
+        colormap: colormap,
+        wm_delete_window: wm_delete_window,
+
+        x: x,
+        y: y,
+        w: w,
+        h: h,
+    })
+}
+
+fn keysym_to_key(keysym: c_uint) -> Key {
+    match keysym {
+        XK_Tab => Key::Tab,
+        XK_Up => Key::Up,
+        XK_Down => Key::Down,
+        XK_Left => Key::Left,
+        XK_Right => Key::Right,
+        XK_Return => Key::Return,
+        XK_Escape => Key::Escape,
+        XK_space => Key::Space,
+        XK_period => Key::Period,
+        XK_BackSpace => Key::Backspace,
+        // Keys from A to Z and from Num0 to Num9 are contiguous
+        XK_a ... XK_z => unsafe { mem::transmute(Key::A as u16 + (keysym - XK_a) as u16) },
+        XK_0 ... XK_9 => unsafe { mem::transmute(Key::Num0 as u16 + (keysym - XK_0) as u16) },
+        _ => Key::Unknown,
+    }
+}
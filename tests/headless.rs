@@ -0,0 +1,53 @@
+//! Drives the game loop through a headless window and checks what it drew,
+//! run with `cargo test --features headless`. Needs libOSMesa.
+
+#![cfg(feature = "headless")]
+
+extern crate hammer;
+
+use std::cell::{Cell, RefCell};
+use std::sync::mpsc::Sender;
+
+use hammer::{Config, Game};
+use hammer::core::math::{Rect, vector};
+use hammer::core::renderer::{self, Drawable, RenderOrder};
+use hammer::window::WindowBuilder;
+use hammer::window::event::Event;
+
+thread_local! {
+    static EVENTS: RefCell<Option<Sender<Event>>> = RefCell::new(None);
+    static FRAMES: Cell<usize> = Cell::new(0);
+}
+
+struct LeftHalfRed;
+
+impl Game for LeftHalfRed {
+    fn update() {}
+
+    fn render() {
+        renderer::rect(Rect::with_min_size(vector(-1.0, -1.0), vector(1.0, 2.0)))
+            .color(1.0, 0.0, 0.0, 1.0)
+            .push(RenderOrder::new(0, 0));
+        renderer::request_screenshot();
+
+        FRAMES.with(|frames| frames.set(frames.get() + 1));
+        // Ends the loop after this frame
+        EVENTS.with(|events| events.borrow().as_ref().unwrap().send(Event::Close).unwrap());
+    }
+}
+
+#[test]
+fn renders_headless() {
+    let window = WindowBuilder::new().size(64, 32).build().unwrap();
+    EVENTS.with(|events| *events.borrow_mut() = Some(window.event_sender()));
+
+    hammer::run_with_window::<LeftHalfRed>(Config::default(), window);
+
+    assert_eq!(FRAMES.with(|frames| frames.get()), 1);
+    let screenshot = renderer::take_screenshot().expect("No frame was read back");
+    assert_eq!(screenshot.size(), (64, 32));
+    assert_eq!(screenshot.pixel(0, 0), [255, 0, 0, 255]);
+    assert_eq!(screenshot.pixel(31, 31), [255, 0, 0, 255]);
+    assert_eq!(screenshot.pixel(32, 0), [0, 0, 0, 255]);
+    assert_eq!(screenshot.pixel(63, 31), [0, 0, 0, 255]);
+}